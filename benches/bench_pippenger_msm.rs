@@ -1,6 +1,14 @@
+use ark_ec::ProjectiveCurve;
+use ark_ff::{fields::BitIteratorBE, PrimeField, UniformRand, Zero};
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
 use std::path::{Path, PathBuf};
-use wasm_zkp_challenge::msm::{read_or_generate_instances, Instance};
+use wasm_zkp_challenge::msm::pippenger::{compute_msm_buckets, compute_msm_buckets_signed};
+use wasm_zkp_challenge::msm::{
+    self, instance_rng, open_or_generate_instances, straus, tuning, BigInt, FixedBaseTable,
+    G1Projective, Instance, ScalarField, DEFAULT_SEED,
+};
+#[cfg(feature = "parallel")]
+use wasm_zkp_challenge::msm::{SplitStrategy, ThreadedMsm};
 
 mod perf;
 
@@ -15,37 +23,90 @@ fn bench_instance_path(count: usize, k: usize) -> PathBuf {
 const INPUT_SIZES: &'static [usize] = &[12];
 
 fn bench_msm(c: &mut Criterion) {
-    let functions: &[(&'static str, bool, &dyn Fn(&Instance))] = &[
+    // Entries that do not fix the window use the table written by tune-window-size, if there is
+    // one, at MSM_WINDOW_TABLE or the default path.
+    let table_path =
+        std::env::var("MSM_WINDOW_TABLE").unwrap_or_else(|_| tuning::WINDOW_TABLE_PATH.to_owned());
+    tuning::set_window_table(tuning::read_window_table_if_exists(table_path).unwrap());
+
+    let functions: Vec<(&'static str, bool, &dyn Fn(&Instance))> = vec![
         ("baseline", false, &|input: &Instance| {
             let _ = input.compute_msm_baseline();
         }),
         ("opt_false_false", false, &|input: &Instance| {
-            let _ = input.compute_msm::<false, false>();
+            let _ = input.compute_msm::<false, false, false>();
         }),
         ("opt_true_false", false, &|input: &Instance| {
-            let _ = input.compute_msm::<true, false>();
+            let _ = input.compute_msm::<true, false, false>();
         }),
         ("opt_true_true", true, &|input: &Instance| {
-            let _ = input.compute_msm::<true, true>();
+            let _ = input.compute_msm::<true, true, false>();
         }),
         ("opt_false_true", true, &|input: &Instance| {
-            let _ = input.compute_msm::<false, true>();
+            let _ = input.compute_msm::<false, true, false>();
+        }),
+        ("buckets", true, &|input: &Instance| {
+            let _ = compute_msm_buckets(&input.points, &input.scalars, None);
+        }),
+        ("buckets_signed", true, &|input: &Instance| {
+            let _ = compute_msm_buckets_signed(&input.points, &input.scalars, None);
+        }),
+        // The kernel of `buckets`, over the windows of the 128-bit GLV halves only.
+        ("opt_true_true_glv", true, &|input: &Instance| {
+            let _ = input.compute_msm::<true, true, true>();
         }),
     ];
 
+    // Threaded variants, on one thread per core unless MSM_THREADS is set.
+    #[cfg(feature = "parallel")]
+    let threaded = {
+        let threads = std::env::var("MSM_THREADS").map_or(0, |t| t.parse().unwrap());
+        [
+            ("threaded_by_window", SplitStrategy::ByWindow),
+            ("threaded_by_points", SplitStrategy::ByPoints),
+            ("threaded_hybrid", SplitStrategy::Hybrid),
+        ]
+        .map(|(name, strategy)| (name, ThreadedMsm::new(threads, strategy).unwrap()))
+    };
+    #[cfg(feature = "parallel")]
+    let threaded_functions = threaded
+        .iter()
+        .map(|(name, msm)| {
+            let function = move |input: &Instance| {
+                let _ = input.compute_msm_threaded(msm);
+            };
+            (*name, function)
+        })
+        .collect::<Vec<_>>();
+    #[cfg(feature = "parallel")]
+    let functions = {
+        let mut functions = functions;
+        functions.extend(
+            threaded_functions
+                .iter()
+                .map(|(name, function)| (*name, true, function as &dyn Fn(&Instance))),
+        );
+        functions
+    };
+
     let mut group = c.benchmark_group("msm");
     for k in INPUT_SIZES.iter() {
-        for (name, enabled, function) in functions {
+        for (name, enabled, function) in &functions {
             // Check to see if the bench is flagged as enabled above.
             if !enabled {
                 continue;
             }
 
+            // Only the instance being benchmarked is read, so the file can be arbitrarily large.
             let path = bench_instance_path(1, *k);
-            let instances = read_or_generate_instances(&path, 1, 1 << k).unwrap();
+            let instance = open_or_generate_instances(&path, 1, 1 << k)
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap();
             // I don't think black_box is needed based on what I am reading in the docs.
             // Shouldn't really hurt anything though, so I'll just leave it.
-            let input = black_box(&instances[0]);
+            let input = black_box(&instance);
 
             group.throughput(Throughput::Elements(1 << k));
             group.bench_with_input(BenchmarkId::new(*name, k), &input, |b, input| {
@@ -58,9 +119,71 @@ fn bench_msm(c: &mut Criterion) {
     group.finish();
 }
 
+/// Sizes at which Straus' method and Pippenger's algorithm are compared, to place
+/// `straus::THRESHOLD` at the crossover.
+const SMALL_SIZES: &'static [usize] = &[2, 4, 8, 16, 32, 64, 96, 128, 192, 256, 384, 512];
+
+fn bench_small_msm(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_msm");
+    for &size in SMALL_SIZES {
+        let instance = Instance::generate_seeded(size, DEFAULT_SEED, size as u64);
+        let (points, scalars) = (&instance.points, &instance.scalars);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("straus", size), &size, |b, _| {
+            b.iter(|| straus::compute_msm_straus(points, scalars))
+        });
+        group.bench_with_input(BenchmarkId::new("dispatch", size), &size, |b, _| {
+            b.iter(|| msm::compute_msm_dispatch(points, scalars))
+        });
+        group.bench_with_input(BenchmarkId::new("opt", size), &size, |b, _| {
+            b.iter(|| msm::compute_msm::<true, true, false>(points, scalars, None))
+        });
+    }
+    group.finish();
+}
+
+/// Double-and-add, the plain scalar multiplication that fixed-base tables replace.
+fn double_and_add(base: &G1Projective, scalar: &BigInt) -> G1Projective {
+    let mut result = G1Projective::zero();
+    for bit in BitIteratorBE::without_leading_zeros(scalar) {
+        result.double_in_place();
+        if bit {
+            result += base;
+        }
+    }
+    result
+}
+
+fn bench_fixed_base(c: &mut Criterion) {
+    let count = 1 << 12;
+    let base = G1Projective::prime_subgroup_generator();
+    let mut rng = instance_rng(DEFAULT_SEED, 0);
+    let scalars = (0..count)
+        .map(|_| ScalarField::rand(&mut rng).into_bigint())
+        .collect::<Vec<_>>();
+    let table = FixedBaseTable::new(base, FixedBaseTable::window_for(count));
+
+    let mut group = c.benchmark_group("fixed_base");
+    group.throughput(Throughput::Elements(count as u64));
+    group.bench_function("double_and_add", |b| {
+        b.iter(|| {
+            let results = scalars
+                .iter()
+                .map(|scalar| double_and_add(&base, scalar))
+                .collect::<Vec<_>>();
+            G1Projective::batch_normalization_into_affine(&results)
+        })
+    });
+    group.bench_function("table", |b| b.iter(|| table.batch_mul(&scalars)));
+    group.bench_function("table_with_precomputation", |b| {
+        b.iter(|| FixedBaseTable::new(base, FixedBaseTable::window_for(count)).batch_mul(&scalars))
+    });
+    group.finish();
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().with_profiler(perf::FlamegraphProfiler::new(100));
-    targets = bench_msm
+    targets = bench_msm, bench_fixed_base, bench_small_msm
 }
 criterion_main!(benches);
@@ -0,0 +1,6 @@
+# Build the wasm package with threads enabled, for the Web Worker pool in www/parallel. Shared memory
+# needs the atomics target features, which in turn need std rebuilt with them on nightly.
+set -e
+RUSTFLAGS="-C target-feature=+atomics,+bulk-memory,+mutable-globals -C link-arg=--max-memory=4294967296" \
+  rustup run nightly wasm-pack build --release --target web --out-dir pkg-parallel \
+  -- --features wasm-parallel -Z build-std=panic_abort,std
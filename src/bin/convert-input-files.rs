@@ -0,0 +1,76 @@
+use ark_ec::ProjectiveCurve;
+use clap::Parser;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use wasm_zkp_challenge::msm::{self, json};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Input path of the instances to convert. Files ending in .json or .jsonl are read as JSON
+    /// Lines, and any other file as a binary instance file.
+    #[clap(short, long, value_parser)]
+    input: String,
+
+    /// Output path for the converted instances. The format is chosen by extension, as for input.
+    #[clap(short, long, value_parser)]
+    output: String,
+
+    /// Include the expected result of each instance, computed with the baseline, in JSON output.
+    #[clap(long, value_parser)]
+    expected: bool,
+
+    /// Store points in compressed form in binary output.
+    #[clap(long, value_parser)]
+    compressed: bool,
+
+    /// Skip validation of the input instances, so that invalid instances can be converted.
+    #[clap(long, value_parser)]
+    unchecked: bool,
+}
+
+fn is_json(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("json" | "jsonl")
+    )
+}
+
+fn main() -> Result<(), msm::Error> {
+    let args = Args::parse();
+    let (input, output) = (Path::new(&args.input), Path::new(&args.output));
+    let validation = if args.unchecked {
+        msm::Validation::Unchecked
+    } else {
+        msm::Validation::Checked
+    };
+
+    let instances: Box<dyn Iterator<Item = Result<msm::Instance, msm::Error>>> = if is_json(input) {
+        let reader = BufReader::new(File::open(input)?);
+        Box::new(json::read_json_lines(reader).map(move |json| json?.to_instance(validation)))
+    } else {
+        Box::new(msm::open_instances(input, validation)?)
+    };
+
+    if is_json(output) {
+        let mut writer = BufWriter::new(File::create(output)?);
+        for instance in instances {
+            let instance = instance?;
+            let expected = args
+                .expected
+                .then(|| instance.compute_msm_baseline().into_affine());
+            json::write_json_line(&mut writer, &instance, expected.as_ref())?;
+        }
+        writer.flush()?;
+    } else {
+        let encoding = if args.compressed {
+            msm::Encoding::Compressed
+        } else {
+            msm::Encoding::Uncompressed
+        };
+        let instances = instances.collect::<Result<Vec<_>, _>>()?;
+        msm::write_instances_with_encoding(output, &instances, false, encoding)?;
+    }
+    Ok(())
+}
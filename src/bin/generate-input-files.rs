@@ -16,14 +16,55 @@ struct Args {
     /// Number of elements, as a power of two, to include in each input vector.
     #[clap(short, long, value_parser, default_value_t = 12)]
     size: usize,
+
+    /// Seed from which the instances are generated. Each instance uses its own stream.
+    #[clap(long, value_parser, default_value_t = msm::DEFAULT_SEED)]
+    seed: u64,
+
+    /// Distribution of the scalars: uniform, zero, one, minus-one, small:<bits>, all-equal,
+    /// sparse-high, window:<c> or mixed.
+    #[clap(long, value_parser, default_value_t = msm::ScalarDistribution::Uniform)]
+    scalars: msm::ScalarDistribution,
+
+    /// Distribution of the points: random, duplicates, negations, with-infinity,
+    /// bucket-collisions or mixed.
+    #[clap(long, value_parser, default_value_t = msm::PointDistribution::Random)]
+    points: msm::PointDistribution,
+
+    /// Store points in compressed form, halving their size at the cost of slower reads.
+    #[clap(long, value_parser)]
+    compressed: bool,
+
+    /// Also record the result of each instance, computed with the baseline, in a sidecar file.
+    #[clap(long, value_parser)]
+    expected: bool,
 }
 
 fn main() -> Result<(), msm::Error> {
     let args = Args::parse();
 
-    let instances: Vec<_> = (0..args.count)
-        .map(|_| msm::Instance::generate(1 << args.size))
-        .collect();
-    msm::write_instances(Path::new(&args.file), &instances, false)?;
+    let encoding = if args.compressed {
+        msm::Encoding::Compressed
+    } else {
+        msm::Encoding::Uncompressed
+    };
+    let path = Path::new(&args.file);
+    let config = msm::GeneratorConfig {
+        scalars: args.scalars,
+        points: args.points,
+    };
+    msm::write_generated_instances(
+        path,
+        args.count,
+        1 << args.size,
+        &config,
+        args.seed,
+        encoding,
+    )?;
+
+    // Writing the file removed any results recorded for a previous file at this path.
+    if args.expected {
+        msm::record_expected_results(path)?;
+    }
     Ok(())
 }
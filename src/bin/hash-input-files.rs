@@ -13,8 +13,8 @@ struct Args {
 fn main() -> Result<(), msm::Error> {
     let args = Args::parse();
 
-    let instances = msm::read_instances(Path::new(&args.file))?;
-    let deserialize_hash = msm::hash(&instances)?;
+    let instances = msm::open_instances(Path::new(&args.file), msm::Validation::Checked)?;
+    let deserialize_hash = msm::hash_instances(instances)?;
     println!("Hash of input files: {:?}", &deserialize_hash);
     Ok(())
 }
@@ -0,0 +1,77 @@
+use clap::{CommandFactory, ErrorKind, Parser};
+use std::time::Instant;
+use wasm_zkp_challenge::msm::pippenger::MAX_WINDOW;
+use wasm_zkp_challenge::msm::{self, tuning};
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// Output path for the window table.
+    #[clap(short, long, value_parser, default_value = tuning::WINDOW_TABLE_PATH)]
+    output: String,
+
+    /// Smallest input size to tune, as a power of two.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(..usize::BITS as i64), default_value_t = 8)]
+    min_size: u32,
+
+    /// Largest input size to tune, as a power of two.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(..usize::BITS as i64), default_value_t = 16)]
+    max_size: u32,
+
+    /// Smallest window size to try.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=MAX_WINDOW as i64), default_value_t = 4)]
+    min_c: u32,
+
+    /// Largest window size to try.
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..=MAX_WINDOW as i64), default_value_t = 16)]
+    max_c: u32,
+
+    /// Number of timed runs of each window size at each input size.
+    #[clap(short, long, value_parser, default_value_t = 5)]
+    rounds: usize,
+}
+
+fn main() -> Result<(), msm::Error> {
+    let args = Args::parse();
+    if args.min_size > args.max_size {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--min-size must not be greater than --max-size",
+            )
+            .exit();
+    }
+    if args.min_c > args.max_c {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--min-c must not be greater than --max-c",
+            )
+            .exit();
+    }
+
+    let config = tuning::TuningConfig {
+        log_sizes: (args.min_size..=args.max_size).collect(),
+        candidates: args.min_c..=args.max_c,
+        rounds: args.rounds,
+        ..Default::default()
+    };
+    let table = tuning::tune_window_table(&config, |instance, c| {
+        let start = Instant::now();
+        let _ = msm::compute_msm::<true, true, false>(
+            &instance.points,
+            &instance.scalars,
+            Some(c as usize),
+        );
+        let elapsed = start.elapsed().as_secs_f64();
+        println!(
+            "2^{} c={}: {:.3} ms",
+            instance.size().trailing_zeros(),
+            c,
+            elapsed * 1e3
+        );
+        elapsed
+    });
+    print!("{}", table);
+    tuning::write_window_table(&args.output, &table)
+}
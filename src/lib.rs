@@ -1,7 +1,15 @@
 use ark_ec::{AffineCurve, ProjectiveCurve};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use js_sys::{Array, Uint8Array};
+#[cfg(feature = "wasm-parallel")]
+use std::cell::UnsafeCell;
+use std::rc::Rc;
+#[cfg(feature = "wasm-parallel")]
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+#[cfg(feature = "wasm-parallel")]
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 
 #[cfg(feature = "debug")]
 use console_error_panic_hook;
@@ -28,9 +36,91 @@ macro_rules! console_log {
 
 pub mod msm;
 
+/// Read the bytes of a field element or scalar from a JS value, checking their length.
+fn js_bytes(value: &JsValue, len: usize) -> Result<Vec<u8>, JsError> {
+    let bytes = value
+        .dyn_ref::<Uint8Array>()
+        .ok_or_else(|| JsError::new("expected a Uint8Array"))?
+        .to_vec();
+    if bytes.len() != len {
+        return Err(msm::Error::InvalidByteLength {
+            expected: len,
+            found: bytes.len(),
+        }
+        .into());
+    }
+    Ok(bytes)
+}
+
+/// Serialize a field element or scalar into a JS byte array.
+fn to_uint8_array<T: CanonicalSerialize>(value: &T) -> Result<Uint8Array, JsError> {
+    let mut bytes = Vec::with_capacity(value.serialized_size());
+    value.serialize(&mut bytes).map_err(msm::Error::from)?;
+    Ok(Uint8Array::from(bytes.as_slice()))
+}
+
+/// Encode a point as an `[x, y, infinity]` array.
+fn point_to_js_array(point: &msm::G1Affine) -> Result<Array, JsError> {
+    let arr = Array::new_with_length(3);
+    arr.set(0, to_uint8_array(&point.x)?.into());
+    arr.set(1, to_uint8_array(&point.y)?.into());
+    arr.set(2, point.infinity.into());
+    Ok(arr)
+}
+
+/// Decode a point from an `[x, y, infinity]` array, without checking that it is on the curve.
+fn point_from_js_array(value: &JsValue, index: usize) -> Result<msm::G1Affine, JsError> {
+    let invalid = || JsError::new(&format!("point {} is not an [x, y, infinity] array", index));
+    let tuple = value.dyn_ref::<Array>().ok_or_else(invalid)?;
+    if tuple.length() != 3 {
+        return Err(invalid());
+    }
+
+    // Check whether the given encoded point is the point at infinity.
+    if tuple.get(2).as_bool().ok_or_else(invalid)? {
+        return Ok(msm::G1Affine::identity());
+    }
+
+    let coordinate = |value: JsValue| -> Result<_, JsError> {
+        let bytes = js_bytes(&value, 48)?;
+        <msm::G1Affine as AffineCurve>::BaseField::deserialize(bytes.as_slice())
+            .map_err(|_| msm::Error::InvalidFieldElement { index }.into())
+    };
+    Ok(msm::G1Affine::new_unchecked(
+        coordinate(tuple.get(0))?,
+        coordinate(tuple.get(1))?,
+    ))
+}
+
+/// Check that the points and scalars passed to an MSM have the same length.
+fn check_lengths(points: &[msm::G1Affine], scalars: &[msm::BigInt]) -> Result<(), JsError> {
+    if points.len() != scalars.len() {
+        return Err(msm::Error::LengthMismatch {
+            points: points.len(),
+            scalars: scalars.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Check that an explicit window size is between 1 and `msm::pippenger::MAX_WINDOW`.
+fn check_window(c: usize) -> Result<(), JsError> {
+    msm::pippenger::check_window(u32::try_from(c).unwrap_or(u32::MAX))?;
+    Ok(())
+}
+
+fn zcash_encoding(compressed: bool) -> msm::Encoding {
+    if compressed {
+        msm::Encoding::Compressed
+    } else {
+        msm::Encoding::Uncompressed
+    }
+}
+
 #[wasm_bindgen]
 pub struct PointVectorInput {
-    point_vec: Vec<msm::G1Affine>,
+    point_vec: Rc<Vec<msm::G1Affine>>,
 }
 
 #[wasm_bindgen]
@@ -40,62 +130,87 @@ impl PointVectorInput {
         init_panic_hook();
         let (point_vec, _) = msm::generate_msm_inputs(size);
 
-        Self { point_vec }
+        Self {
+            point_vec: Rc::new(point_vec),
+        }
     }
 
     #[wasm_bindgen(js_name = "toJsArray")]
-    pub fn to_js_array(&self) -> Array {
+    pub fn to_js_array(&self) -> Result<Array, JsError> {
         let arr = Array::new_with_length(self.point_vec.len() as u32);
-        for (i, point) in (&self.point_vec).into_iter().enumerate() {
-            let x = point.x;
-            let y = point.y;
-            let is_infinity = point.infinity;
-
-            let mut x_bytes: Vec<u8> = Vec::with_capacity(48);
-            x.serialize(&mut x_bytes).unwrap();
-            let mut y_bytes: Vec<u8> = Vec::with_capacity(48);
-            y.serialize(&mut y_bytes).unwrap();
-
-            let point = Array::new_with_length(3);
-            point.set(0, Uint8Array::from(x_bytes.as_slice()).into());
-            point.set(1, Uint8Array::from(y_bytes.as_slice()).into());
-            point.set(2, is_infinity.into());
-            arr.set(i as u32, point.into());
+        for (i, point) in self.point_vec.iter().enumerate() {
+            arr.set(i as u32, point_to_js_array(point)?.into());
         }
-        arr
+        Ok(arr)
     }
 
+    /// Decode points from `[x, y, infinity]` arrays, checking that each is a valid point in G1.
     #[wasm_bindgen(js_name = "fromJsArray")]
-    pub fn from_js_array(arr: &Array) -> Self {
-        let mut point_vec = Vec::<msm::G1Affine>::with_capacity(arr.length() as usize);
-        for i in 0..arr.length() {
-            let tuple = Array::from(&arr.get(i));
-
-            // Check whether the given encoded point is the point at infinity.
-            let is_infinity = tuple.get(2).as_bool().unwrap();
-            if is_infinity {
-                point_vec.push(msm::G1Affine::identity());
-                continue;
-            }
+    pub fn from_js_array(arr: &Array) -> Result<PointVectorInput, JsError> {
+        init_panic_hook();
+        let point_vec = (0..arr.length())
+            .map(|i| point_from_js_array(&arr.get(i), i as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        msm::validate_points(&point_vec)?;
+        Ok(Self {
+            point_vec: Rc::new(point_vec),
+        })
+    }
 
-            let x_bytes: Vec<u8> = Uint8Array::from(tuple.get(0)).to_vec();
-            let x =
-                <msm::G1Affine as AffineCurve>::BaseField::deserialize(x_bytes.as_slice()).unwrap();
+    /// Encode the points in the big-endian ZCash format used by other BLS12-381 libraries,
+    /// concatenated into a single array of 48 (compressed) or 96 bytes per point.
+    #[wasm_bindgen(js_name = "toZcashBytes")]
+    pub fn to_zcash_bytes(&self, compressed: bool) -> Vec<u8> {
+        msm::zcash::serialize_points(&self.point_vec, zcash_encoding(compressed))
+    }
 
-            let y_bytes: Vec<u8> = Uint8Array::from(tuple.get(1)).to_vec();
-            let y =
-                <msm::G1Affine as AffineCurve>::BaseField::deserialize(y_bytes.as_slice()).unwrap();
+    /// Decode points in the ZCash format, checking that each is a valid point in G1.
+    #[wasm_bindgen(js_name = "fromZcashBytes")]
+    pub fn from_zcash_bytes(bytes: &[u8], compressed: bool) -> Result<PointVectorInput, JsError> {
+        init_panic_hook();
+        let point_vec = msm::zcash::deserialize_points(bytes, zcash_encoding(compressed))?;
+        Ok(Self {
+            point_vec: Rc::new(point_vec),
+        })
+    }
 
-            point_vec.push(msm::G1Affine::new_unchecked(x, y));
-        }
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.point_vec.len()
+    }
 
-        Self { point_vec }
+    /// Pack the points into a single array of 96 bytes per point, in the same little-endian
+    /// encoding as instance files. Much faster than [PointVectorInput::to_js_array] for large
+    /// vectors, as only one JS object is created.
+    #[wasm_bindgen(js_name = "toBytes")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(msm::packed::pack_points(&self.point_vec)?)
+    }
+
+    /// Unpack points produced by [PointVectorInput::to_bytes], checking that each is valid.
+    #[wasm_bindgen(js_name = "fromBytes")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<PointVectorInput, JsError> {
+        init_panic_hook();
+        let point_vec = msm::packed::unpack_points(bytes, msm::Validation::Checked)?;
+        Ok(Self {
+            point_vec: Rc::new(point_vec),
+        })
+    }
+
+    /// Unpack points without validating them. Only use with trusted inputs.
+    #[wasm_bindgen(js_name = "fromBytesUnchecked")]
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> Result<PointVectorInput, JsError> {
+        init_panic_hook();
+        let point_vec = msm::packed::unpack_points(bytes, msm::Validation::Unchecked)?;
+        Ok(Self {
+            point_vec: Rc::new(point_vec),
+        })
     }
 }
 
 #[wasm_bindgen]
 pub struct ScalarVectorInput {
-    scalar_vec: Vec<msm::BigInt>,
+    scalar_vec: Rc<Vec<msm::BigInt>>,
 }
 
 #[wasm_bindgen]
@@ -105,36 +220,123 @@ impl ScalarVectorInput {
         init_panic_hook();
         let (_, scalar_vec) = msm::generate_msm_inputs(size);
 
-        Self { scalar_vec }
+        Self {
+            scalar_vec: Rc::new(scalar_vec),
+        }
     }
 
     #[wasm_bindgen(js_name = "toJsArray")]
-    pub fn to_js_array(&self) -> Array {
+    pub fn to_js_array(&self) -> Result<Array, JsError> {
         let arr = Array::new_with_length(self.scalar_vec.len() as u32);
-        for (i, scalar) in (&self.scalar_vec).into_iter().enumerate() {
-            let mut bytes: Vec<u8> = Vec::with_capacity(32);
-            scalar.serialize(&mut bytes).unwrap();
-            arr.set(i as u32, Uint8Array::from(bytes.as_slice()).into());
+        for (i, scalar) in self.scalar_vec.iter().enumerate() {
+            arr.set(i as u32, to_uint8_array(scalar)?.into());
         }
-        arr
+        Ok(arr)
     }
 
+    /// Decode little-endian scalars, checking that each is below the scalar field modulus.
     #[wasm_bindgen(js_name = "fromJsArray")]
-    pub fn from_js_array(arr: &Array) -> Self {
-        let mut scalar_vec = Vec::<msm::BigInt>::with_capacity(arr.length() as usize);
-        for i in 0..arr.length() {
-            let bytes: Vec<u8> = Uint8Array::from(arr.get(i)).to_vec();
-            scalar_vec.push(msm::BigInt::deserialize(bytes.as_slice()).unwrap());
+    pub fn from_js_array(arr: &Array) -> Result<ScalarVectorInput, JsError> {
+        init_panic_hook();
+        let scalar_vec = (0..arr.length())
+            .map(|i| {
+                let bytes = js_bytes(&arr.get(i), 32)?;
+                Ok(msm::BigInt::deserialize(bytes.as_slice()).map_err(msm::Error::from)?)
+            })
+            .collect::<Result<Vec<_>, JsError>>()?;
+        msm::validate_scalars(&scalar_vec)?;
+        Ok(Self {
+            scalar_vec: Rc::new(scalar_vec),
+        })
+    }
+
+    /// Encode the scalars as concatenated 32-byte big-endian integers.
+    #[wasm_bindgen(js_name = "toBigEndianBytes")]
+    pub fn to_big_endian_bytes(&self) -> Vec<u8> {
+        msm::zcash::serialize_scalars(&self.scalar_vec)
+    }
+
+    /// Decode concatenated 32-byte big-endian scalars, checking that each is below the modulus.
+    #[wasm_bindgen(js_name = "fromBigEndianBytes")]
+    pub fn from_big_endian_bytes(bytes: &[u8]) -> Result<ScalarVectorInput, JsError> {
+        init_panic_hook();
+        let scalar_vec = msm::zcash::deserialize_scalars(bytes)?;
+        Ok(Self {
+            scalar_vec: Rc::new(scalar_vec),
+        })
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.scalar_vec.len()
+    }
+
+    /// Pack the scalars into a single array of 32 little-endian bytes per scalar.
+    #[wasm_bindgen(js_name = "toBytes")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(msm::packed::pack_scalars(&self.scalar_vec)?)
+    }
+
+    /// Unpack scalars produced by [ScalarVectorInput::to_bytes], checking that each is below the
+    /// scalar field modulus.
+    #[wasm_bindgen(js_name = "fromBytes")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<ScalarVectorInput, JsError> {
+        init_panic_hook();
+        let scalar_vec = msm::packed::unpack_scalars(bytes, msm::Validation::Checked)?;
+        Ok(Self {
+            scalar_vec: Rc::new(scalar_vec),
+        })
+    }
+
+    /// Construct a vector of zero scalars, to be filled in through [ScalarVectorInput::view].
+    pub fn zeroed(length: usize) -> Self {
+        init_panic_hook();
+        Self {
+            scalar_vec: Rc::new(vec![msm::BigInt::default(); length]),
         }
+    }
 
-        Self { scalar_vec }
+    /// View the packed scalars directly in wasm memory, without copying. The view can be read, or
+    /// written with `set` to fill in the scalars, after which [ScalarVectorInput::validate] should
+    /// be called on untrusted data.
+    ///
+    /// The view is invalidated by any allocation in wasm, which may grow the memory, so it must be
+    /// used immediately and not kept. If the scalars are shared with an instance, they are copied
+    /// first so that writes through the view only affect this vector.
+    ///
+    /// Only available on little-endian targets, including wasm, where the scalars in memory are
+    /// exactly their packed encoding.
+    #[cfg(target_endian = "little")]
+    pub fn view(&mut self) -> Uint8Array {
+        let scalars = Rc::make_mut(&mut self.scalar_vec);
+        // Safety: the view is only valid until the next allocation, as documented above.
+        unsafe { Uint8Array::view(msm::packed::scalars_as_bytes_mut(scalars)) }
+    }
+
+    /// Check that every scalar is below the scalar field modulus.
+    pub fn validate(&self) -> Result<(), JsError> {
+        Ok(msm::validate_scalars(&self.scalar_vec)?)
     }
 }
 
+/// An instance, holding its points and scalars in shared handles.
+///
+/// The point and scalar vectors returned by its accessors share the instance's data rather than
+/// copying it, so they are cheap to obtain even for large instances.
 #[wasm_bindgen]
+#[derive(Clone)]
 pub struct InstanceObject {
-    points: Vec<msm::G1Affine>,
-    scalars: Vec<msm::BigInt>,
+    points: Rc<Vec<msm::G1Affine>>,
+    scalars: Rc<Vec<msm::BigInt>>,
+}
+
+impl From<msm::Instance> for InstanceObject {
+    fn from(instance: msm::Instance) -> Self {
+        Self {
+            points: Rc::new(instance.points),
+            scalars: Rc::new(instance.scalars),
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -147,14 +349,14 @@ impl InstanceObject {
     #[wasm_bindgen]
     pub fn points(&self) -> PointVectorInput {
         PointVectorInput {
-            point_vec: self.points.clone(),
+            point_vec: Rc::clone(&self.points),
         }
     }
 
     #[wasm_bindgen]
     pub fn scalars(&self) -> ScalarVectorInput {
         ScalarVectorInput {
-            scalar_vec: self.scalars.clone(),
+            scalar_vec: Rc::clone(&self.scalars),
         }
     }
 }
@@ -171,13 +373,15 @@ impl InstanceObjectVector {
         self.instances.len()
     }
 
-    // Copy the instance to hand off the the JS VM.
-    // Note that this copies the full undderlying data, which may be quite large.
-    pub fn at(&self, i: usize) -> InstanceObject {
-        InstanceObject {
-            points: self.instances[i].points.clone(),
-            scalars: self.instances[i].scalars.clone(),
-        }
+    /// Get a handle to the instance at index `i`, sharing its data with this vector.
+    pub fn at(&self, i: usize) -> Result<InstanceObject, JsError> {
+        self.instances.get(i).cloned().ok_or_else(|| {
+            JsError::new(&format!(
+                "index {} is out of range for {} instances",
+                i,
+                self.instances.len()
+            ))
+        })
     }
 }
 
@@ -189,82 +393,681 @@ pub struct PointOutput {
 #[wasm_bindgen]
 impl PointOutput {
     #[wasm_bindgen(js_name = "toJsArray")]
-    pub fn to_js_array(&self) -> Array {
-        let x = self.point.x;
-        let y = self.point.y;
-        let is_infinity = self.point.infinity;
-
-        let mut x_bytes: Vec<u8> = Vec::with_capacity(48);
-        x.serialize(&mut x_bytes).unwrap();
-        let mut y_bytes: Vec<u8> = Vec::with_capacity(48);
-        y.serialize(&mut y_bytes).unwrap();
+    pub fn to_js_array(&self) -> Result<Array, JsError> {
+        point_to_js_array(&self.point)
+    }
 
-        let point = Array::new_with_length(3);
-        point.set(0, Uint8Array::from(x_bytes.as_slice()).into());
-        point.set(1, Uint8Array::from(y_bytes.as_slice()).into());
-        point.set(2, is_infinity.into());
-        point
+    /// Encode the point in the big-endian ZCash format used by other BLS12-381 libraries.
+    #[wasm_bindgen(js_name = "toZcashBytes")]
+    pub fn to_zcash_bytes(&self, compressed: bool) -> Vec<u8> {
+        msm::zcash::serialize_point(&self.point, zcash_encoding(compressed))
     }
 }
 
+/// Deserialize a list of instances, checking that all points and scalars are valid.
 #[wasm_bindgen]
-pub fn deserialize_msm_inputs(data: &[u8]) -> InstanceObjectVector {
+pub fn deserialize_msm_inputs(data: &[u8]) -> Result<InstanceObjectVector, JsError> {
     init_panic_hook();
-    let instances = Vec::<msm::Instance>::deserialize_unchecked(data).unwrap();
-    InstanceObjectVector {
-        instances: instances
-            .into_iter()
-            .map(|i| InstanceObject {
-                points: i.points,
-                scalars: i.scalars,
-            })
-            .collect(),
+    deserialize_msm_inputs_with(data, msm::Validation::Checked)
+}
+
+/// Deserialize a list of instances without validating them. Only use with trusted inputs.
+#[wasm_bindgen]
+pub fn deserialize_msm_inputs_unchecked(data: &[u8]) -> Result<InstanceObjectVector, JsError> {
+    init_panic_hook();
+    deserialize_msm_inputs_with(data, msm::Validation::Unchecked)
+}
+
+fn deserialize_msm_inputs_with(
+    data: &[u8],
+    validation: msm::Validation,
+) -> Result<InstanceObjectVector, JsError> {
+    let instances = msm::InstanceReader::new(data, validation)?
+        .map(|instance| instance.map(InstanceObject::from))
+        .collect::<Result<_, _>>()?;
+    Ok(InstanceObjectVector { instances })
+}
+
+/// Decoder for instance files supplied in chunks, yielding one instance at a time.
+///
+/// Only the instance currently being decoded is buffered, which allows running over files that
+/// would not fit into memory if deserialized all at once.
+#[wasm_bindgen]
+pub struct InstanceStream {
+    decoder: msm::InstanceDecoder,
+}
+
+impl Default for InstanceStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl InstanceStream {
+    /// Construct a stream that checks that all points and scalars are valid.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        init_panic_hook();
+        Self {
+            decoder: msm::InstanceDecoder::new(msm::Validation::Checked),
+        }
+    }
+
+    /// Construct a stream that does not validate its input. Only use with trusted inputs.
+    pub fn unchecked() -> Self {
+        init_panic_hook();
+        Self {
+            decoder: msm::InstanceDecoder::new(msm::Validation::Unchecked),
+        }
+    }
+
+    /// Add the next chunk of the file.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.decoder.feed(chunk);
+    }
+
+    /// Get the next instance, or undefined if more data needs to be fed first.
+    pub fn next(&mut self) -> Result<Option<InstanceObject>, JsError> {
+        Ok(self.decoder.next_instance()?.map(InstanceObject::from))
+    }
+
+    /// Whether every instance in the file has been read.
+    #[wasm_bindgen(getter)]
+    pub fn finished(&self) -> bool {
+        self.decoder.is_finished()
+    }
+
+    /// Check that the file was complete, to be called after the last chunk has been fed.
+    pub fn finish(&self) -> Result<(), JsError> {
+        Ok(self.decoder.finish()?)
     }
 }
 
+/// Generate the instance at position `index` in the reproducible sequence given by `seed`.
 #[wasm_bindgen]
-pub fn generate_msm_inputs(size: usize) -> InstanceObject {
+pub fn generate_msm_inputs(size: usize, seed: u64, index: u64) -> InstanceObject {
     init_panic_hook();
-    let (points, scalars) = msm::generate_msm_inputs(size);
-    InstanceObject { points, scalars }
+    msm::Instance::generate_seeded(size, seed, index).into()
 }
 
 #[wasm_bindgen]
 pub fn compute_msm_baseline(
     point_vec: &PointVectorInput,
     scalar_vec: &ScalarVectorInput,
-) -> PointOutput {
+) -> Result<PointOutput, JsError> {
     init_panic_hook();
-    PointOutput {
+    check_lengths(&point_vec.point_vec, &scalar_vec.scalar_vec)?;
+    Ok(PointOutput {
         point: msm::compute_msm_baseline(&point_vec.point_vec, &scalar_vec.scalar_vec)
             .into_affine(),
-    }
+    })
 }
 
 #[wasm_bindgen]
-pub fn compute_msm(point_vec: &PointVectorInput, scalar_vec: &ScalarVectorInput) -> PointOutput {
+pub fn compute_msm(
+    point_vec: &PointVectorInput,
+    scalar_vec: &ScalarVectorInput,
+) -> Result<PointOutput, JsError> {
     init_panic_hook();
-    PointOutput {
-        point: msm::compute_msm::<true, true>(&point_vec.point_vec, &scalar_vec.scalar_vec, None)
-            .into_affine(),
-    }
+    check_lengths(&point_vec.point_vec, &scalar_vec.scalar_vec)?;
+    Ok(PointOutput {
+        point: msm::compute_msm::<true, true, false>(
+            &point_vec.point_vec,
+            &scalar_vec.scalar_vec,
+            None,
+        )
+        .into_affine(),
+    })
 }
 
+/// Compute the MSM with window size `c`, from 1 to 20. If `signed` is true, scalars are recoded
+/// into signed digits, which halves the number of buckets per window.
 #[wasm_bindgen]
 pub fn compute_msm_with_c(
     point_vec: &PointVectorInput,
     scalar_vec: &ScalarVectorInput,
     c: usize,
-) -> PointOutput {
+    signed: Option<bool>,
+) -> Result<PointOutput, JsError> {
     init_panic_hook();
-    PointOutput {
-        point: msm::compute_msm::<true, true>(
+    check_lengths(&point_vec.point_vec, &scalar_vec.scalar_vec)?;
+    check_window(c)?;
+    if signed == Some(true) {
+        // Signed digits are only correct for scalars below the modulus.
+        msm::validate_scalars(&scalar_vec.scalar_vec)?;
+        let point = msm::pippenger::compute_msm_buckets_signed(
+            &point_vec.point_vec,
+            &scalar_vec.scalar_vec,
+            Some(c as u32),
+        )?;
+        return Ok(PointOutput {
+            point: point.into_affine(),
+        });
+    }
+    Ok(PointOutput {
+        point: msm::compute_msm::<true, true, false>(
             &point_vec.point_vec,
             &scalar_vec.scalar_vec,
             Some(c),
         )
         .into_affine(),
+    })
+}
+
+/// Compute the MSM of an instance with the baseline implementation, without copying its data.
+#[wasm_bindgen]
+pub fn compute_msm_baseline_instance(instance: &InstanceObject) -> Result<PointOutput, JsError> {
+    init_panic_hook();
+    check_lengths(&instance.points, &instance.scalars)?;
+    Ok(PointOutput {
+        point: msm::compute_msm_baseline(&instance.points, &instance.scalars).into_affine(),
+    })
+}
+
+/// Compute the MSM of an instance without copying its data, with window size `c` if given. If
+/// `glv` is true, scalars are split in half with the GLV endomorphism first, which halves the
+/// number of windows at the cost of doubling the number of points.
+#[wasm_bindgen]
+pub fn compute_msm_instance(
+    instance: &InstanceObject,
+    c: Option<usize>,
+    glv: Option<bool>,
+) -> Result<PointOutput, JsError> {
+    init_panic_hook();
+    check_lengths(&instance.points, &instance.scalars)?;
+    if let Some(c) = c {
+        check_window(c)?;
+    }
+    let point = if glv == Some(true) {
+        // The decomposition is only defined for scalars below the modulus.
+        msm::validate_scalars(&instance.scalars)?;
+        msm::compute_msm::<true, true, true>(&instance.points, &instance.scalars, c)
+    } else {
+        msm::compute_msm::<true, true, false>(&instance.points, &instance.scalars, c)
+    };
+    Ok(PointOutput {
+        point: point.into_affine(),
+    })
+}
+
+/// Compute the MSM of an instance with the algorithm suited to its size, which for small
+/// instances is much faster than `compute_msm_instance`.
+#[wasm_bindgen]
+pub fn compute_msm_dispatch_instance(instance: &InstanceObject) -> Result<PointOutput, JsError> {
+    init_panic_hook();
+    check_lengths(&instance.points, &instance.scalars)?;
+    Ok(PointOutput {
+        point: msm::compute_msm_dispatch(&instance.points, &instance.scalars).into_affine(),
+    })
+}
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// Install a window table, in the text format written by `tune-window-size` and
+/// `tune_window_table`, for `compute_msm` to consult when no window size is given.
+#[wasm_bindgen]
+pub fn set_window_table(table: &str) -> Result<(), JsError> {
+    init_panic_hook();
+    msm::tuning::set_window_table(Some(table.parse()?));
+    Ok(())
+}
+
+/// The installed window table in its text format, if any.
+#[wasm_bindgen]
+pub fn window_table() -> Option<String> {
+    msm::tuning::window_table().map(|table| table.to_string())
+}
+
+/// Find the fastest window size on this platform for inputs of 2^min_size to 2^max_size points,
+/// trying sizes min_c to max_c, and install the resulting table. The table is returned in its text
+/// format, so that it can be stored and installed again later with `set_window_table`.
+#[wasm_bindgen]
+pub fn tune_window_table(
+    min_size: u32,
+    max_size: u32,
+    min_c: u32,
+    max_c: u32,
+    rounds: usize,
+) -> Result<String, JsError> {
+    init_panic_hook();
+    if min_size > max_size || max_size >= usize::BITS {
+        return Err(JsError::new("invalid range of input sizes"));
+    }
+    if min_c > max_c || min_c == 0 || max_c > msm::pippenger::MAX_WINDOW {
+        return Err(JsError::new(&format!(
+            "window sizes must be between 1 and {}",
+            msm::pippenger::MAX_WINDOW
+        )));
+    }
+    let config = msm::tuning::TuningConfig {
+        log_sizes: (min_size..=max_size).collect(),
+        candidates: min_c..=max_c,
+        rounds,
+        ..Default::default()
+    };
+    let table = msm::tuning::tune_window_table(&config, |instance, c| {
+        let start = performance_now();
+        let _ = msm::compute_msm::<true, true, false>(
+            &instance.points,
+            &instance.scalars,
+            Some(c as usize),
+        );
+        performance_now() - start
+    });
+    let text = table.to_string();
+    msm::tuning::set_window_table(Some(table));
+    Ok(text)
+}
+
+/// A list of scalar vectors, for computing several MSMs over the same points together.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct ScalarVectorBatch {
+    scalar_vecs: Vec<Rc<Vec<msm::BigInt>>>,
+}
+
+#[wasm_bindgen]
+impl ScalarVectorBatch {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        init_panic_hook();
+        Self::default()
+    }
+
+    /// Add a scalar vector to the batch. Its data is shared, not copied.
+    pub fn push(&mut self, scalar_vec: &ScalarVectorInput) {
+        self.scalar_vecs.push(Rc::clone(&scalar_vec.scalar_vec));
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.scalar_vecs.len()
+    }
+}
+
+/// The results of a batch of MSMs, in the order of their scalar vectors.
+#[wasm_bindgen]
+pub struct PointOutputVector {
+    points: Vec<msm::G1Affine>,
+}
+
+#[wasm_bindgen]
+impl PointOutputVector {
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn at(&self, i: usize) -> Result<PointOutput, JsError> {
+        let point = self.points.get(i).ok_or_else(|| {
+            JsError::new(&format!(
+                "index {} is out of range for {} results",
+                i,
+                self.points.len()
+            ))
+        })?;
+        Ok(PointOutput { point: *point })
+    }
+}
+
+/// Compute the MSM of the points with each scalar vector in the batch, sharing the bucket sorting
+/// and inversions between them, with window size `c` if given.
+#[wasm_bindgen]
+pub fn compute_msm_batch(
+    point_vec: &PointVectorInput,
+    batch: &ScalarVectorBatch,
+    c: Option<u32>,
+) -> Result<PointOutputVector, JsError> {
+    init_panic_hook();
+    for scalar_vec in &batch.scalar_vecs {
+        check_lengths(&point_vec.point_vec, scalar_vec)?;
+    }
+    if let Some(c) = c {
+        msm::pippenger::check_window(c)?;
+    }
+    let scalar_vecs = batch
+        .scalar_vecs
+        .iter()
+        .map(|scalar_vec| scalar_vec.as_slice())
+        .collect::<Vec<_>>();
+    let results = msm::compute_msm_batch(&point_vec.point_vec, &scalar_vecs, c);
+    Ok(PointOutputVector {
+        points: msm::G1Projective::batch_normalization_into_affine(&results),
+    })
+}
+
+/// Shifted multiples of a vector of bases, precomputed once for any number of MSMs against them.
+///
+/// The table can be saved with `toBytes`, for example to IndexedDB, and loaded again with
+/// `fromBytes` on later visits instead of being recomputed.
+#[wasm_bindgen]
+pub struct PrecomputedBasesObject {
+    bases: msm::PrecomputedBases,
+}
+
+#[wasm_bindgen]
+impl PrecomputedBasesObject {
+    /// Precompute the table for the given bases, with window size `c` if given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        point_vec: &PointVectorInput,
+        c: Option<u32>,
+    ) -> Result<PrecomputedBasesObject, JsError> {
+        init_panic_hook();
+        Ok(Self {
+            bases: msm::PrecomputedBases::new(&point_vec.point_vec, c)?,
+        })
+    }
+
+    /// Number of bases.
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.bases.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn window(&self) -> u32 {
+        self.bases.window()
+    }
+
+    /// Compute the MSM of the bases with the given scalars, which may be fewer than the bases.
+    #[wasm_bindgen(js_name = "computeMsm")]
+    pub fn compute_msm(&self, scalar_vec: &ScalarVectorInput) -> Result<PointOutput, JsError> {
+        init_panic_hook();
+        if scalar_vec.scalar_vec.len() > self.bases.len() {
+            return Err(JsError::new(&format!(
+                "{} scalars given for {} bases",
+                scalar_vec.scalar_vec.len(),
+                self.bases.len()
+            )));
+        }
+        Ok(PointOutput {
+            point: self.bases.compute_msm(&scalar_vec.scalar_vec).into_affine(),
+        })
+    }
+
+    /// Serialize the table.
+    #[wasm_bindgen(js_name = "toBytes")]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsError> {
+        Ok(self.bases.to_bytes()?)
+    }
+
+    /// Load a table produced by [PrecomputedBasesObject::to_bytes], checking that each point is
+    /// valid.
+    #[wasm_bindgen(js_name = "fromBytes")]
+    pub fn from_bytes(bytes: &[u8]) -> Result<PrecomputedBasesObject, JsError> {
+        init_panic_hook();
+        let bases = msm::PrecomputedBases::from_bytes(bytes, msm::Validation::Checked)?;
+        Ok(Self { bases })
+    }
+
+    /// Load a table without validating its points. Only use with trusted inputs, such as a table
+    /// this page stored itself.
+    #[wasm_bindgen(js_name = "fromBytesUnchecked")]
+    pub fn from_bytes_unchecked(bytes: &[u8]) -> Result<PrecomputedBasesObject, JsError> {
+        init_panic_hook();
+        let bases = msm::PrecomputedBases::from_bytes(bytes, msm::Validation::Unchecked)?;
+        Ok(Self { bases })
+    }
+}
+
+/// An MSM computed a slice at a time, so that large MSMs do not block the browser's main thread.
+///
+/// Call `step` repeatedly, for example from `requestIdleCallback` or between awaited promises,
+/// until it returns true, then call `finish` to get the result. `finish` completes any remaining
+/// work itself, so it can also be called early.
+#[wasm_bindgen]
+pub struct MsmJob {
+    points: Rc<Vec<msm::G1Affine>>,
+    scalars: Rc<Vec<msm::BigInt>>,
+    job: Option<msm::pippenger::MsmJob>,
+}
+
+#[wasm_bindgen]
+impl MsmJob {
+    /// Start an MSM over the given instance, with window size `c` if given. The instance's data is
+    /// shared, not copied.
+    #[wasm_bindgen(constructor)]
+    pub fn new(instance: &InstanceObject, c: Option<u32>) -> Result<MsmJob, JsError> {
+        init_panic_hook();
+        Self::start(Rc::clone(&instance.points), Rc::clone(&instance.scalars), c)
+    }
+
+    /// Start an MSM over the given points and scalars, with window size `c` if given.
+    #[wasm_bindgen(js_name = "fromVectors")]
+    pub fn from_vectors(
+        point_vec: &PointVectorInput,
+        scalar_vec: &ScalarVectorInput,
+        c: Option<u32>,
+    ) -> Result<MsmJob, JsError> {
+        init_panic_hook();
+        Self::start(
+            Rc::clone(&point_vec.point_vec),
+            Rc::clone(&scalar_vec.scalar_vec),
+            c,
+        )
+    }
+
+    fn start(
+        points: Rc<Vec<msm::G1Affine>>,
+        scalars: Rc<Vec<msm::BigInt>>,
+        c: Option<u32>,
+    ) -> Result<MsmJob, JsError> {
+        check_lengths(&points, &scalars)?;
+        let job = msm::pippenger::MsmJob::new(points.len(), c, false)?;
+        Ok(Self {
+            points,
+            scalars,
+            job: Some(job),
+        })
+    }
+
+    fn job(&mut self) -> Result<&mut msm::pippenger::MsmJob, JsError> {
+        self.job
+            .as_mut()
+            .ok_or_else(|| JsError::new("the job has already finished"))
+    }
+
+    /// Process up to `budget` points, returning whether all of the work is done. Each point costs
+    /// one addition, so the time taken by a step is roughly proportional to the budget.
+    pub fn step(&mut self, budget: usize) -> Result<bool, JsError> {
+        let (points, scalars) = (Rc::clone(&self.points), Rc::clone(&self.scalars));
+        Ok(self.job()?.step(&points, &scalars, budget))
+    }
+
+    /// Fraction of the work done so far, from 0 to 1.
+    #[wasm_bindgen(getter)]
+    pub fn progress(&self) -> f64 {
+        self.job.as_ref().map_or(1.0, |job| job.progress())
+    }
+
+    /// Complete any remaining work and return the result. Can only be called once.
+    pub fn finish(&mut self) -> Result<PointOutput, JsError> {
+        let job = self
+            .job
+            .take()
+            .ok_or_else(|| JsError::new("the job has already finished"))?;
+        Ok(PointOutput {
+            point: job.finish(&self.points, &self.scalars).into_affine(),
+        })
+    }
+}
+
+/// An MSM split by window between a pool of workers sharing this module's memory, each running the
+/// batch-affine bucket kernel of `msm::pippenger` over its windows.
+///
+/// Requires a build with the `wasm-parallel` feature and wasm threads enabled, as done by
+/// `build-parallel.sh`. The job copies the points and scalars into the shared memory and registers
+/// itself under `id`, so each worker only needs the id and a task index below `taskCount` to pass
+/// to `run_msm_task`. Once every task has run, `finish` combines the results. Freeing the job
+/// unregisters it, after which its tasks can no longer be run. See `www/parallel/pool.js` for a
+/// pool that drives this.
+#[cfg(feature = "wasm-parallel")]
+#[wasm_bindgen]
+pub struct ParallelMsmJob {
+    id: u32,
+    job: Arc<SharedMsmJob>,
+}
+
+/// The inputs and tasks of a [ParallelMsmJob], shared with the workers.
+#[cfg(feature = "wasm-parallel")]
+struct SharedMsmJob {
+    points: Vec<msm::G1Affine>,
+    scalars: Vec<msm::BigInt>,
+    c: u32,
+    tasks: Vec<WindowTask>,
+}
+
+/// The windows one worker computes.
+#[cfg(feature = "wasm-parallel")]
+struct WindowTask {
+    windows: std::ops::Range<u32>,
+    /// Set by the one worker that claims the task, so that no other worker writes `sums`.
+    claimed: AtomicBool,
+    /// Written by the worker that claimed the task, before it sets `done`.
+    sums: UnsafeCell<Vec<msm::G1Projective>>,
+    /// Set with release ordering once `sums` is written, so that a thread that reads it with
+    /// acquire ordering also sees the sums.
+    done: AtomicBool,
+}
+
+// Safety: `sums` is only written by the worker that won the claim on `claimed`, and only read
+// after an acquire load of `done` observes that worker's release store.
+#[cfg(feature = "wasm-parallel")]
+unsafe impl Sync for WindowTask {}
+
+/// Live parallel jobs by id, through which workers find the tasks they are asked to run.
+#[cfg(feature = "wasm-parallel")]
+static PARALLEL_JOBS: Mutex<Vec<(u32, Arc<SharedMsmJob>)>> = Mutex::new(Vec::new());
+
+#[cfg(feature = "wasm-parallel")]
+static NEXT_JOB_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Lock the registry of parallel jobs. The lock is only held for a lookup, insertion or removal, so
+/// it is taken by spinning, as waiting on it would trap on the browser's main thread.
+#[cfg(feature = "wasm-parallel")]
+fn parallel_jobs() -> MutexGuard<'static, Vec<(u32, Arc<SharedMsmJob>)>> {
+    loop {
+        match PARALLEL_JOBS.try_lock() {
+            Ok(jobs) => return jobs,
+            Err(TryLockError::Poisoned(e)) => return e.into_inner(),
+            Err(TryLockError::WouldBlock) => std::hint::spin_loop(),
+        }
+    }
+}
+
+#[cfg(feature = "wasm-parallel")]
+#[wasm_bindgen]
+impl ParallelMsmJob {
+    /// Split an MSM over the given instance into at most `workers` tasks, using window size `c` if
+    /// given.
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        instance: &InstanceObject,
+        c: Option<u32>,
+        workers: usize,
+    ) -> Result<ParallelMsmJob, JsError> {
+        init_panic_hook();
+        check_lengths(&instance.points, &instance.scalars)?;
+        let c = c.unwrap_or_else(|| msm::pippenger::default_window(instance.points.len()));
+        msm::pippenger::check_window(c)?;
+        if workers == 0 {
+            return Err(JsError::new("at least one worker is required"));
+        }
+        let tasks = msm::pippenger::split_windows(c, workers, false)
+            .into_iter()
+            .map(|windows| WindowTask {
+                windows,
+                claimed: AtomicBool::new(false),
+                sums: UnsafeCell::new(Vec::new()),
+                done: AtomicBool::new(false),
+            })
+            .collect();
+        let job = Arc::new(SharedMsmJob {
+            points: instance.points.to_vec(),
+            scalars: instance.scalars.to_vec(),
+            c,
+            tasks,
+        });
+        let id = NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed);
+        parallel_jobs().push((id, Arc::clone(&job)));
+        Ok(Self { id, job })
+    }
+
+    /// Id of the job, for workers to pass to `run_msm_task`.
+    #[wasm_bindgen(getter)]
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Number of tasks, each to be run once with `run_msm_task`.
+    #[wasm_bindgen(getter, js_name = "taskCount")]
+    pub fn task_count(&self) -> usize {
+        self.job.tasks.len()
+    }
+
+    /// Combine the results of the tasks. Every task must have run.
+    pub fn finish(&self) -> Result<PointOutput, JsError> {
+        let mut sums = Vec::new();
+        for task in &self.job.tasks {
+            if !task.done.load(Ordering::Acquire) {
+                return Err(JsError::new("not every task has run"));
+            }
+            // Safety: the worker no longer touches the sums once `done` is set, and the acquire
+            // load above makes its writes visible here.
+            sums.extend_from_slice(unsafe { &*task.sums.get() });
+        }
+        Ok(PointOutput {
+            point: msm::pippenger::combine_windows(&sums, self.job.c).into_affine(),
+        })
+    }
+}
+
+#[cfg(feature = "wasm-parallel")]
+impl Drop for ParallelMsmJob {
+    fn drop(&mut self) {
+        parallel_jobs().retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// Run task `task` of the live `ParallelMsmJob` with the given id on the calling worker. Each task
+/// can only be run once.
+#[cfg(feature = "wasm-parallel")]
+#[wasm_bindgen]
+pub fn run_msm_task(job: u32, task: usize) -> Result<(), JsError> {
+    let job = parallel_jobs()
+        .iter()
+        .find(|(id, _)| *id == job)
+        .map(|(_, job)| Arc::clone(job))
+        .ok_or_else(|| JsError::new("no such parallel MSM job"))?;
+    let task = job
+        .tasks
+        .get(task)
+        .ok_or_else(|| JsError::new("no such task in the parallel MSM job"))?;
+    if task
+        .claimed
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return Err(JsError::new("the task has already been run"));
     }
+    let sums = msm::pippenger::window_sums(
+        &job.points,
+        &job.scalars,
+        job.c,
+        task.windows.clone(),
+        false,
+    );
+    // Safety: winning the claim above makes this worker the only writer, and nothing reads the
+    // sums until `done` is set.
+    unsafe { *task.sums.get() = sums };
+    task.done.store(true, Ordering::Release);
+    Ok(())
 }
 
 #[cfg(feature = "coverage")]
@@ -1,30 +1,178 @@
+use ark_bls12_381::Fq;
 pub use ark_bls12_381::{G1Affine, G1Projective};
 use ark_ec::{msm, AffineCurve, ProjectiveCurve};
-use ark_ff::{fields::BitIteratorLE, PrimeField, UniformRand, Zero};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+use ark_ff::{PrimeField, UniformRand};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalDeserializeWithFlags, CanonicalSerialize, Read, SWFlags,
+    SerializationError, Write,
+};
+use ark_std::rand::{Rng, SeedableRng};
+use ark_std::{cfg_chunks, cfg_into_iter, cfg_iter};
 #[cfg(feature = "std")]
 use blake3::Hash;
 #[cfg(feature = "std")]
 use bytes::BufMut;
+use rand_chacha::ChaCha20Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 #[cfg(feature = "std")]
 use std::fs::{create_dir_all, File};
 #[cfg(feature = "std")]
-use std::path::Path;
-
+use std::io::{BufReader, BufWriter, Seek, SeekFrom};
 #[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+pub mod format;
+use format::{write_frames, Header, Preamble};
+pub use format::{InstanceDecoder, InstanceReader};
+pub mod batch;
+pub use batch::compute_msm_batch;
+pub mod distribution;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod zcash;
+pub use distribution::{GeneratorConfig, PointDistribution, ScalarDistribution};
+pub mod fixed_base;
+pub use fixed_base::FixedBaseTable;
+pub mod glv;
+pub mod packed;
+pub mod pippenger;
+pub mod precomputed;
+pub use precomputed::PrecomputedBases;
+pub mod straus;
+#[cfg(feature = "parallel")]
+pub mod threaded;
+pub mod tuning;
+#[cfg(feature = "parallel")]
+pub use threaded::{SplitStrategy, ThreadedMsm};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("could not serialize")]
     SerializationError(#[from] ark_serialize::SerializationError),
 
+    #[cfg(feature = "std")]
     #[error("io error")]
     IoError(#[from] std::io::Error),
+
+    #[cfg(feature = "json")]
+    #[error("invalid JSON")]
+    JsonError(#[from] serde_json::Error),
+
+    #[cfg(feature = "parallel")]
+    #[error("could not start thread pool")]
+    ThreadPoolError(#[from] rayon::ThreadPoolBuildError),
+
+    #[error("invalid hex value {0:?}")]
+    InvalidHex(String),
+
+    #[error("expected result is invalid")]
+    InvalidExpected(#[source] Box<Error>),
+
+    #[error("instance has {points} points but {scalars} scalars")]
+    LengthMismatch { points: usize, scalars: usize },
+
+    #[error("point {index} is not on the curve")]
+    PointNotOnCurve { index: usize },
+
+    #[error("point {index} is not in the prime-order subgroup")]
+    PointNotInSubgroup { index: usize },
+
+    #[error("scalar {index} is not below the scalar field modulus")]
+    ScalarOutOfRange { index: usize },
+
+    #[error("unsupported instance file version {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("instance file is for curve {found:#x}, expected {expected:#x} (BLS12-381 G1)")]
+    CurveMismatch { expected: u16, found: u16 },
+
+    #[error("unsupported instance file flags {0:#x}")]
+    UnsupportedFlags(u16),
+
+    #[error("instance file header lists {expected} instances but the file contains {found}")]
+    CountMismatch { expected: u64, found: u64 },
+
+    #[error("instance {index} has size {found} but the file header lists {expected}")]
+    SizeMismatch {
+        index: usize,
+        expected: u64,
+        found: u64,
+    },
+
+    #[error("instance {index} occupies {found} bytes but its frame is {expected} bytes long")]
+    FrameLengthMismatch {
+        index: usize,
+        expected: u64,
+        found: u64,
+    },
+
+    #[error("cannot append {requested:?} instances to a file of {found:?} instances")]
+    EncodingMismatch {
+        requested: Encoding,
+        found: Encoding,
+    },
+
+    #[error("invalid byte length {found}, expected {expected} bytes per element")]
+    InvalidByteLength { expected: usize, found: usize },
+
+    #[error("point {index} has invalid encoding flags")]
+    InvalidPointFlags { index: usize },
+
+    #[error("point {index} has a coordinate that is not below the base field modulus")]
+    InvalidFieldElement { index: usize },
+
+    #[error("unknown distribution {0:?}")]
+    InvalidDistribution(String),
+
+    #[error("invalid precomputed bases")]
+    InvalidPrecomputedBases,
+
+    #[error("window size {c} is not between 1 and {}", pippenger::MAX_WINDOW)]
+    InvalidWindow { c: u32 },
+
+    #[error("invalid window table entry on line {line}")]
+    InvalidWindowTable { line: usize },
+
+    #[error("instance data ended after {read} instances")]
+    UnexpectedEnd { read: u64 },
+
+    #[error("instance {index} is invalid")]
+    InvalidInstance {
+        index: usize,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+/// Controls whether deserialized inputs are validated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// Check that every point is on the curve and in the prime-order subgroup, and that every
+    /// scalar is below the scalar field modulus.
+    Checked,
+    /// Trust the input. This is much faster, but should only be used with inputs from a trusted
+    /// source, as invalid points silently produce incorrect MSM results.
+    Unchecked,
+}
+
+/// Encoding of the points in serialized instances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Both coordinates are stored, taking 96 bytes per point. Fastest to read.
+    Uncompressed,
+    /// Only the x-coordinate and the sign of y are stored, taking 48 bytes per point. The
+    /// y-coordinate is recovered on read, at the cost of a square root per point.
+    Compressed,
 }
 
 // Define ScalarField and BigInt type aliases to avoid lengthy fully-qualified names.
 pub type ScalarField = <G1Affine as AffineCurve>::ScalarField;
 pub type BigInt = <ScalarField as PrimeField>::BigInt;
 
+/// Seed used when generating instances without an explicitly provided seed.
+pub const DEFAULT_SEED: u64 = 0;
+
 /// A struct wrapping the input for an msm problem
 #[derive(Debug, CanonicalSerialize, CanonicalDeserialize)]
 pub struct Instance {
@@ -38,66 +186,231 @@ impl Instance {
         Self { points, scalars }
     }
 
+    /// Generate the instance at position `index` in the reproducible sequence given by `seed`.
+    pub fn generate_seeded(size: usize, seed: u64, index: u64) -> Self {
+        Self::generate_with_config(size, &GeneratorConfig::default(), seed, index)
+    }
+
+    /// Generate the instance at position `index` in the reproducible sequence given by `seed`,
+    /// drawing its inputs from the distributions in `config`.
+    pub fn generate_with_config(
+        size: usize,
+        config: &GeneratorConfig,
+        seed: u64,
+        index: u64,
+    ) -> Self {
+        let (points, scalars) =
+            generate_msm_inputs_with_config(size, config, &mut instance_rng(seed, index));
+        Self { points, scalars }
+    }
+
     pub fn compute_msm_baseline(&self) -> G1Projective {
         compute_msm_baseline(&self.points, &self.scalars)
     }
 
-    pub fn compute_msm<const COMPLETE: bool, const BATCH_ACC_BUCKETS: bool>(&self) -> G1Projective {
-        compute_msm::<COMPLETE, BATCH_ACC_BUCKETS>(&self.points, &self.scalars, None)
+    pub fn compute_msm<const COMPLETE: bool, const BATCH_ACC_BUCKETS: bool, const GLV: bool>(
+        &self,
+    ) -> G1Projective {
+        compute_msm::<COMPLETE, BATCH_ACC_BUCKETS, GLV>(&self.points, &self.scalars, None)
+    }
+
+    /// Compute the MSM on the threads of the given pool.
+    #[cfg(feature = "parallel")]
+    pub fn compute_msm_threaded(&self, msm: &ThreadedMsm) -> Result<G1Projective, Error> {
+        msm.compute_msm(&self.points, &self.scalars, None)
     }
 
     /// Get the size of the instance
     pub fn size(&self) -> usize {
         self.points.len()
     }
-}
 
-pub fn generate_msm_inputs(size: usize) -> (Vec<G1Affine>, Vec<BigInt>) {
-    let mut rng = ark_std::test_rng();
+    /// Serialize the instance with its points in the given encoding.
+    pub fn serialize_with_encoding<W: Write>(
+        &self,
+        writer: W,
+        encoding: Encoding,
+    ) -> Result<(), Error> {
+        // We use unchecked because this is not an adversarial environment and it is way faster.
+        match encoding {
+            Encoding::Uncompressed => self.serialize_unchecked(writer)?,
+            Encoding::Compressed => self.serialize(writer)?,
+        }
+        Ok(())
+    }
 
-    let scalar_vec = (0..size)
-        .map(|_| ScalarField::rand(&mut rng).into_bigint())
-        .collect::<Vec<_>>();
+    /// Deserialize an instance with its points in the given encoding, without validating it.
+    ///
+    /// Compressed points each cost a square root in the base field to recover, several times the
+    /// cost of reading them, so with the `parallel` feature they are recovered on all threads.
+    pub fn deserialize_with_encoding<R: Read>(
+        mut reader: R,
+        encoding: Encoding,
+    ) -> Result<Self, Error> {
+        match encoding {
+            Encoding::Uncompressed => Ok(Self::deserialize_unchecked(reader)?),
+            Encoding::Compressed => {
+                // Read all of the x-coordinates first, so that the points can be recovered in
+                // parallel.
+                let count = u64::deserialize(&mut reader)?;
+                let mut compressed = Vec::new();
+                for _ in 0..count {
+                    compressed.push(Fq::deserialize_with_flags::<_, SWFlags>(&mut reader)?);
+                }
+                let points = decompress_points(&compressed)?;
+                let scalars = Vec::<BigInt>::deserialize_unchecked(&mut reader)?;
+                Ok(Self { points, scalars })
+            }
+        }
+    }
 
-    // Vector of multiples 2^i & G_1, used to precompute the "doubling" portion of double and add.
-    // TODO(victor): This could be improved by implementing a more optimal fixed base multiplcation
-    // routine such as fixed base comb.
-    let g_multiples = {
-        let mut x = G1Projective::prime_subgroup_generator();
-        let mut multiples = vec![x];
+    /// Check that the instance is well-formed, returning the first problem found.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.points.len() != self.scalars.len() {
+            return Err(Error::LengthMismatch {
+                points: self.points.len(),
+                scalars: self.scalars.len(),
+            });
+        }
+        validate_points(&self.points)?;
+        validate_scalars(&self.scalars)
+    }
+}
 
-        // TODO: Don't hardcode that constant.
-        for _ in 0..ScalarField::MODULUS_BIT_SIZE {
-            x.double_in_place();
-            multiples.push(x);
+/// Check that every point is on the curve and in the prime-order subgroup.
+pub fn validate_points(points: &[G1Affine]) -> Result<(), Error> {
+    for (index, point) in points.iter().enumerate() {
+        if !point.is_on_curve() {
+            return Err(Error::PointNotOnCurve { index });
         }
-        G1Projective::batch_normalization_into_affine(&multiples)
-    };
+        if !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(Error::PointNotInSubgroup { index });
+        }
+    }
+    Ok(())
+}
 
-    // Generate a number of random multipliers to apply to G_1 to generate a set of random bases.
-    let factor_vec = (0..size)
-        .map(|_| ScalarField::rand(&mut rng).into_bigint())
-        .collect::<Vec<_>>();
+/// Check that every scalar is below the scalar field modulus.
+pub fn validate_scalars(scalars: &[BigInt]) -> Result<(), Error> {
+    for (index, scalar) in scalars.iter().enumerate() {
+        if ScalarField::from_bigint(*scalar).is_none() {
+            return Err(Error::ScalarOutOfRange { index });
+        }
+    }
+    Ok(())
+}
 
-    // Compute the multiples of G_1 using the precomputed tables of 2^i multiples.
-    let point_vec = factor_vec
-        .iter()
-        .map(|r| {
-            let bits = BitIteratorLE::new(r);
-            let mut p = G1Projective::zero();
-            for (i, b) in bits.enumerate() {
-                if b {
-                    p.add_assign_mixed(&g_multiples[i]);
-                }
+/// Recover points from their x-coordinates and flags, as read from the compressed encoding. Each
+/// point takes its own square root, as unlike inversions they cannot share work, so the points are
+/// spread across threads instead.
+///
+/// The subgroup is not checked here. That is left to [Instance::validate].
+fn decompress_points(compressed: &[(Fq, SWFlags)]) -> Result<Vec<G1Affine>, Error> {
+    let points = cfg_iter!(compressed)
+        .map(|(x, flags)| {
+            if flags.is_infinity() {
+                return Some(G1Affine::identity());
             }
-            p
+            let greatest = flags.is_positive() == Some(true);
+            G1Affine::get_point_from_x(*x, greatest)
         })
         .collect::<Vec<_>>();
+    // Collected in full so that the error reports the first invalid point, whichever thread found
+    // it.
+    points
+        .into_iter()
+        .enumerate()
+        .map(|(index, point)| point.ok_or(Error::PointNotOnCurve { index }))
+        .collect()
+}
+
+/// Generate `count` distinct instances of the given size, reproducible from `seed`.
+pub fn generate_instances(count: usize, size: usize, seed: u64) -> Vec<Instance> {
+    generate_instances_with_config(count, size, &GeneratorConfig::default(), seed)
+}
+
+/// Generate `count` distinct instances of the given size from the distributions in `config`,
+/// reproducible from `seed`.
+pub fn generate_instances_with_config(
+    count: usize,
+    size: usize,
+    config: &GeneratorConfig,
+    seed: u64,
+) -> Vec<Instance> {
+    // Each instance has its own RNG stream, so they can be generated in any order.
+    cfg_into_iter!(0..count)
+        .map(|i| Instance::generate_with_config(size, config, seed, i as u64))
+        .collect()
+}
+
+/// Construct the RNG used to generate the instance at position `index` from `seed`.
+///
+/// Each instance draws from its own ChaCha20 stream, so the instances in a file are distinct and
+/// any one of them can be regenerated without generating the ones before it.
+pub fn instance_rng(seed: u64, index: u64) -> ChaCha20Rng {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    rng.set_stream(index);
+    rng
+}
+
+/// Generate inputs from the fixed test RNG. Every call returns the same vectors.
+pub fn generate_msm_inputs(size: usize) -> (Vec<G1Affine>, Vec<BigInt>) {
+    generate_msm_inputs_with_rng(size, &mut ark_std::test_rng())
+}
+
+/// Generate random inputs, drawing all randomness from the given RNG.
+pub fn generate_msm_inputs_with_rng<R: Rng + ?Sized>(
+    size: usize,
+    rng: &mut R,
+) -> (Vec<G1Affine>, Vec<BigInt>) {
+    generate_msm_inputs_with_config(size, &GeneratorConfig::default(), rng)
+}
+
+/// Generate inputs from the distributions in `config`, drawing all randomness from the given RNG.
+pub fn generate_msm_inputs_with_config<R: Rng + ?Sized>(
+    size: usize,
+    config: &GeneratorConfig,
+    rng: &mut R,
+) -> (Vec<G1Affine>, Vec<BigInt>) {
+    let mut scalar_vec = config.scalars.sample(size, rng);
+
+    // Generate a number of random multipliers to apply to G_1 to generate a set of random bases.
+    let factor_vec = (0..size)
+        .map(|_| ScalarField::rand(rng).into_bigint())
+        .collect::<Vec<_>>();
 
-    let point_vec = G1Projective::batch_normalization_into_affine(&point_vec);
+    // Compute the multiples of G_1 using a table sized for the number of points.
+    let table = FixedBaseTable::new(
+        G1Projective::prime_subgroup_generator(),
+        FixedBaseTable::window_for(size),
+    );
+    let mut point_vec = table.batch_mul(&factor_vec);
+    config.points.apply(&mut point_vec, &mut scalar_vec, rng);
     return (point_vec, scalar_vec);
 }
 
+/// Convert points to affine form, splitting the work across threads with the `parallel` feature.
+///
+/// Normalization is exact, so the result is the same however the work is split.
+pub(crate) fn batch_normalize(points: &[G1Projective]) -> Vec<G1Affine> {
+    // Each chunk shares a single inversion, so chunks are kept large enough to amortize it.
+    const CHUNK_SIZE: usize = 1 << 12;
+    cfg_chunks!(points, CHUNK_SIZE)
+        .flat_map(G1Projective::batch_normalization_into_affine)
+        .collect()
+}
+
+/// Extract the `width`-bit digit of the scalar starting at bit `start`. `width` is at most 32.
+pub(crate) fn scalar_window(scalar: &BigInt, start: u32, width: u32) -> usize {
+    let limbs = scalar.as_ref();
+    let (limb, shift) = ((start / 64) as usize, start % 64);
+    let mut digit = limbs[limb] >> shift;
+    if shift + width > 64 && limb + 1 < limbs.len() {
+        digit |= limbs[limb + 1] << (64 - shift);
+    }
+    (digit & ((1 << width) - 1)) as usize
+}
+
 /// Currently using Pippenger's algorithm for multi-scalar multiplication (MSM)
 pub fn compute_msm_baseline(point_vec: &[G1Affine], scalar_vec: &[BigInt]) -> G1Projective {
     msm::VariableBaseMSM::msm(
@@ -110,14 +423,44 @@ pub fn compute_msm_baseline(point_vec: &[G1Affine], scalar_vec: &[BigInt]) -> G1
 }
 
 /// Locally optimized version of the variable base MSM algorithm.
-pub fn compute_msm<const COMPLETE: bool, const BATCH_ACC_BUCKETS: bool>(
+///
+/// Without an explicit window size `c`, the window from the table installed with
+/// [tuning::set_window_table] is used, falling back to the default heuristic. No table is
+/// installed unless the caller opts in, so results only depend on global state for callers that do;
+/// to avoid it, pass the window from [tuning::WindowTable::lookup] as `c` instead.
+///
+/// With `GLV`, each scalar is first split into two 128-bit halves with [glv::decompose], and
+/// [glv::compute_msm_glv] runs the batch-affine bucket kernel on the points and their images under
+/// the endomorphism over the 128 bits of the halves only, in place of `COMPLETE` and
+/// `BATCH_ACC_BUCKETS`, as the optimized kernel always covers every bit of the scalar field.
+/// Scalars must then be below the scalar field modulus, and `c` at most [pippenger::MAX_WINDOW].
+pub fn compute_msm<const COMPLETE: bool, const BATCH_ACC_BUCKETS: bool, const GLV: bool>(
     point_vec: &[G1Affine],
     scalar_vec: &[BigInt],
     c: Option<usize>,
 ) -> G1Projective {
+    if GLV {
+        return glv::compute_msm_glv(
+            point_vec,
+            scalar_vec,
+            c.map(|c| u32::try_from(c).unwrap_or(u32::MAX)),
+        );
+    }
+    let c = c.or_else(|| tuning::tuned_window(point_vec.len()).map(|c| c as usize));
     msm::MultiExp::compute_msm_opt::<COMPLETE, BATCH_ACC_BUCKETS>(point_vec, scalar_vec, c)
 }
 
+/// Compute the MSM with the algorithm suited to its size: Straus' method for inputs of up to
+/// [straus::THRESHOLD] points, where the buckets of Pippenger's algorithm cost more than they save,
+/// and [compute_msm] otherwise.
+pub fn compute_msm_dispatch(point_vec: &[G1Affine], scalar_vec: &[BigInt]) -> G1Projective {
+    if point_vec.len() <= straus::THRESHOLD {
+        straus::compute_msm_straus(point_vec, scalar_vec)
+    } else {
+        compute_msm::<true, true, false>(point_vec, scalar_vec, None)
+    }
+}
+
 /// Load input vectors from the filesystem if they exist in the given directory.
 /// If not, generate and save new input vectors of the requests size.
 #[cfg(feature = "std")]
@@ -133,50 +476,358 @@ pub fn read_or_generate_instances<P: AsRef<Path>>(
     };
 
     // Generate and write the new instances to the intended directory.
-    let generated: Vec<_> = (0..count).map(|_| Instance::generate(size)).collect();
+    let generated = generate_instances(count, size, DEFAULT_SEED);
     write_instances(&path, &generated, false)?;
 
     Ok(generated)
 }
 
+/// Open the instances file at the given path, first generating it if it does not exist.
+///
+/// Unlike [read_or_generate_instances], instances are generated and read one at a time, so this
+/// can be used with files too large to fit in memory.
+#[cfg(feature = "std")]
+pub fn open_or_generate_instances<P: AsRef<Path>>(
+    path: P,
+    count: usize,
+    size: usize,
+) -> Result<InstanceReader<BufReader<File>>, Error> {
+    match open_instances(&path, Validation::Checked) {
+        Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => (),
+        result => return result,
+    };
+
+    write_generated_instances(
+        &path,
+        count,
+        size,
+        &GeneratorConfig::default(),
+        DEFAULT_SEED,
+        Encoding::Uncompressed,
+    )?;
+    open_instances(&path, Validation::Checked)
+}
+
+/// Generate `count` instances from `seed` and write them to the given path, one at a time.
+///
+/// The file is opened once and each instance is written as a frame as soon as it is generated.
+/// Expected results recorded for the file are removed, as they no longer match it.
+#[cfg(feature = "std")]
+pub fn write_generated_instances<P: AsRef<Path>>(
+    path: P,
+    count: usize,
+    size: usize,
+    config: &GeneratorConfig,
+    seed: u64,
+    encoding: Encoding,
+) -> Result<(), Error> {
+    match path.as_ref().parent() {
+        Some(dir) => create_dir_all(dir)?,
+        None => (),
+    };
+    remove_expected_results(&path)?;
+
+    let mut header = Header::for_instances(&[], encoding);
+    let mut writer = BufWriter::new(File::create(&path)?);
+    header.write(&mut writer)?;
+    for i in 0..count {
+        let instance = [Instance::generate_with_config(size, config, seed, i as u64)];
+        write_frames(&mut writer, &instance, encoding)?;
+        header.extend(&instance);
+    }
+
+    // As when appending, the count is only written once every frame is, so that an interrupted
+    // write leaves the instances before it readable.
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    header.write(&file)?;
+    Ok(())
+}
+
 #[cfg(feature = "std")]
 pub fn write_instances<P: AsRef<Path>>(
     path: P,
     instances: &[Instance],
     append: bool,
+) -> Result<(), Error> {
+    write_instances_with_encoding(path, instances, append, Encoding::Uncompressed)
+}
+
+/// Write instances with their points in the given encoding.
+///
+/// When appending, the encoding must match that of the instances already in the file. Expected
+/// results recorded for the file are removed, as they no longer match it.
+#[cfg(feature = "std")]
+pub fn write_instances_with_encoding<P: AsRef<Path>>(
+    path: P,
+    instances: &[Instance],
+    append: bool,
+    encoding: Encoding,
 ) -> Result<(), Error> {
     // If the target directory does not exist, create it.
     match path.as_ref().parent() {
         Some(dir) => create_dir_all(dir)?,
         None => (),
     };
+    remove_expected_results(&path)?;
 
-    // If append is true, open in append mode. Otherwise truncate.
-    let file = if append {
-        File::options().append(true).create(true).open(path)?
-    } else {
-        File::create(path)?
+    // If append is true and there are existing instances, add to them. Otherwise truncate.
+    if append && matches!(path.as_ref().metadata(), Ok(m) if m.len() > 0) {
+        return append_instances(path, instances, encoding);
+    }
+    serialize_instances(&File::create(path)?, instances, encoding)
+}
+
+/// Append instances to an existing file, rewriting it in the current format if it is older.
+#[cfg(feature = "std")]
+fn append_instances<P: AsRef<Path>>(
+    path: P,
+    instances: &[Instance],
+    encoding: Encoding,
+) -> Result<(), Error> {
+    let mut file = File::options().read(true).write(true).open(path)?;
+    let mut header = match Preamble::read(&mut file)? {
+        Preamble::Header(header) if header.version == format::FORMAT_VERSION => {
+            header.check_supported()?;
+            if header.encoding() != encoding {
+                return Err(Error::EncodingMismatch {
+                    requested: encoding,
+                    found: header.encoding(),
+                });
+            }
+            header
+        }
+        _ => {
+            // Older layouts cannot be extended in place, so convert the existing instances.
+            file.seek(SeekFrom::Start(0))?;
+            let existing = deserialize_instances(BufReader::new(&file), Validation::Unchecked)?;
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            serialize_instances(&file, &existing, encoding)?;
+            Header::for_instances(&existing, encoding)
+        }
     };
 
-    // We use unchecked because this is not an adversarial environment and it is way faster.
-    instances.serialize_unchecked(&file)?;
+    // Write the new frames before updating the header, so that an interrupted append leaves the
+    // previously written instances readable.
+    file.seek(SeekFrom::End(0))?;
+    write_frames(&file, instances, encoding)?;
+    header.extend(instances);
+    file.seek(SeekFrom::Start(0))?;
+    header.write(&file)?;
     Ok(())
 }
 
+/// Serialize a list of instances, preceded by a header describing them.
+pub fn serialize_instances<W: Write>(
+    mut writer: W,
+    instances: &[Instance],
+    encoding: Encoding,
+) -> Result<(), Error> {
+    Header::for_instances(instances, encoding).write(&mut writer)?;
+    write_frames(&mut writer, instances, encoding)
+}
+
+/// Read and validate the instances in the given file.
 #[cfg(feature = "std")]
 pub fn read_instances<P: AsRef<Path>>(path: P) -> Result<Vec<Instance>, Error> {
-    let file = File::open(path)?;
+    open_instances(path, Validation::Checked)?.collect()
+}
+
+/// Open the given file to read its instances one at a time.
+#[cfg(feature = "std")]
+pub fn open_instances<P: AsRef<Path>>(
+    path: P,
+    validation: Validation,
+) -> Result<InstanceReader<BufReader<File>>, Error> {
+    InstanceReader::new(BufReader::new(File::open(path)?), validation)
+}
 
-    // We use unchecked because this is not an adversarial environment and it is way faster.
-    let instances = Vec::<Instance>::deserialize_unchecked(&file)?;
-    Ok(instances)
+/// Read the instances in the given file without validating them.
+///
+/// This is considerably faster than [read_instances] and is suitable for files written by this
+/// crate, but gives incorrect results, rather than an error, on corrupted or malicious input.
+#[cfg(feature = "std")]
+pub fn read_instances_unchecked<P: AsRef<Path>>(path: P) -> Result<Vec<Instance>, Error> {
+    open_instances(path, Validation::Unchecked)?.collect()
+}
+
+/// Deserialize a list of instances, validating each one if requested.
+///
+/// Accepts files in the current format as well as older layouts. See [format] for details.
+pub fn deserialize_instances<R: Read>(
+    reader: R,
+    validation: Validation,
+) -> Result<Vec<Instance>, Error> {
+    InstanceReader::new(reader, validation)?.collect()
 }
 
 #[cfg(feature = "std")]
 pub fn hash<E: CanonicalSerialize>(elements: &[E]) -> Result<Hash, Error> {
     let mut buffer = vec![].writer();
     elements.serialize_unchecked(&mut buffer)?;
-    Ok(blake3::hash(&buffer.into_inner()))
+    let mut hasher = blake3::Hasher::new();
+    update_hasher(&mut hasher, &buffer.into_inner());
+    Ok(hasher.finalize())
+}
+
+/// Hash the instances from the reader one at a time. Gives the same result as [hash] over the
+/// full list of instances.
+#[cfg(feature = "std")]
+pub fn hash_instances<R: Read>(instances: InstanceReader<R>) -> Result<Hash, Error> {
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = Vec::new();
+
+    // Match the length prefix written when serializing a slice.
+    instances.remaining().serialize(&mut buffer)?;
+    hasher.update(&buffer);
+    for instance in instances {
+        buffer.clear();
+        instance?.serialize_unchecked(&mut buffer)?;
+        update_hasher(&mut hasher, &buffer);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Add the data to the hash, using multiple threads with the `parallel` feature. The hash is the
+/// same either way.
+#[cfg(feature = "std")]
+fn update_hasher(hasher: &mut blake3::Hasher, data: &[u8]) {
+    #[cfg(feature = "parallel")]
+    hasher.update_rayon(data);
+    #[cfg(not(feature = "parallel"))]
+    hasher.update(data);
+}
+
+/// Path of the sidecar file holding the expected results for the instance file at `path`.
+///
+/// The sidecar is named after the instance file with `.expected` appended.
+#[cfg(feature = "std")]
+pub fn expected_results_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".expected");
+    PathBuf::from(name)
+}
+
+/// Compute the result of every instance in the file with [compute_msm_baseline] and record them
+/// in the sidecar file given by [expected_results_path].
+#[cfg(feature = "std")]
+pub fn record_expected_results<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let results = open_instances(&path, Validation::Checked)?
+        .map(|instance| Ok(instance?.compute_msm_baseline()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let results = batch_normalize(&results);
+
+    let mut writer = BufWriter::new(File::create(expected_results_path(path))?);
+    results.serialize(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Remove the expected results recorded for the instance file at `path`, if there are any.
+#[cfg(feature = "std")]
+fn remove_expected_results<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    match std::fs::remove_file(expected_results_path(path)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Read the expected results recorded for the instance file at `path`, if there are any.
+#[cfg(feature = "std")]
+pub fn read_expected_results<P: AsRef<Path>>(path: P) -> Result<Option<Vec<G1Affine>>, Error> {
+    match File::open(expected_results_path(path)) {
+        Ok(file) => Ok(Some(Vec::<G1Affine>::deserialize(BufReader::new(file))?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// An instance for which the MSM under test disagreed with the expected result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub index: usize,
+    pub expected: G1Affine,
+    pub actual: G1Affine,
+}
+
+/// Outcome of running an MSM implementation over the instances in a file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Number of instances checked.
+    pub checked: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl VerifyReport {
+    /// Whether every instance produced the expected result.
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Run `msm` over every instance in the file and report the instances where its result differs
+/// from the expected one.
+///
+/// Expected results are taken from the sidecar written by [record_expected_results] if it exists,
+/// and are otherwise computed with [compute_msm_baseline].
+#[cfg(feature = "std")]
+pub fn verify_instances<P, F>(path: P, mut msm: F) -> Result<VerifyReport, Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Instance) -> G1Projective,
+{
+    let instances = open_instances(&path, Validation::Checked)?;
+    let recorded = read_expected_results(&path)?;
+    if let Some(recorded) = &recorded {
+        if recorded.len() as u64 != instances.remaining() {
+            return Err(Error::CountMismatch {
+                expected: instances.remaining(),
+                found: recorded.len() as u64,
+            });
+        }
+    }
+
+    let mut report = VerifyReport::default();
+    for (index, instance) in instances.enumerate() {
+        let instance = instance?;
+        let expected = match &recorded {
+            Some(recorded) => recorded[index],
+            None => instance.compute_msm_baseline().into_affine(),
+        };
+        let actual = msm(&instance).into_affine();
+        if actual != expected {
+            report.mismatches.push(Mismatch {
+                index,
+                expected,
+                actual,
+            });
+        }
+        report.checked += 1;
+    }
+    Ok(report)
+}
+
+/// Inputs of the given size drawn from the named distributions, from the first stream of the
+/// default seed, for tests comparing MSMs against the baseline.
+#[cfg(test)]
+pub(crate) fn test_inputs(
+    size: usize,
+    scalars: &str,
+    points: &str,
+) -> (Vec<G1Affine>, Vec<BigInt>) {
+    let config = GeneratorConfig {
+        scalars: scalars.parse().unwrap(),
+        points: points.parse().unwrap(),
+    };
+    generate_msm_inputs_with_config(size, &config, &mut instance_rng(DEFAULT_SEED, 0))
+}
+
+/// Inputs of the given size with `mixed` scalars and points, which cover the edge cases of every
+/// distribution.
+#[cfg(test)]
+pub(crate) fn mixed_inputs(size: usize) -> (Vec<G1Affine>, Vec<BigInt>) {
+    test_inputs(size, "mixed", "mixed")
 }
 
 #[cfg(test)]
@@ -214,7 +865,7 @@ mod test {
     fn optimized_msm_doesnt_panic() -> Result<(), Error> {
         let instances = read_or_generate_instances(&test_instance_path(K), 1, SIZE)?;
         let start = Instant::now();
-        let res = instances[0].compute_msm::<true, true>();
+        let res = instances[0].compute_msm::<true, true, false>();
         let duration = start.elapsed();
         println!("msm_opt with SIZE 1<<{}: {:?}", K, duration);
         println!("\n msm_opt = {:?}\n", res.into_affine());
@@ -226,11 +877,251 @@ mod test {
     fn optimized_and_baseline_agree() -> Result<(), Error> {
         let instances = read_or_generate_instances(&test_instance_path(K), 1, SIZE)?;
         let res_base = instances[0].compute_msm_baseline();
-        let res_opt = instances[0].compute_msm::<true, true>();
+        let res_opt = instances[0].compute_msm::<true, true, false>();
         assert_eq!(res_base, res_opt);
         Ok(())
     }
 
+    #[test]
+    fn dispatch_and_baseline_agree_at_every_small_size() {
+        let (points, scalars) = mixed_inputs(1024);
+        for size in 1..=1024 {
+            let (points, scalars) = (&points[..size], &scalars[..size]);
+            assert_eq!(
+                compute_msm_dispatch(points, scalars),
+                compute_msm_baseline(points, scalars),
+                "size {}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_generation_is_reproducible_and_distinct() {
+        let first = generate_instances(2, 1 << 4, 7);
+        let second = generate_instances(2, 1 << 4, 7);
+        assert_eq!(first[0].points, second[0].points);
+        assert_eq!(first[1].scalars, second[1].scalars);
+        assert_ne!(first[0].points, first[1].points);
+        assert_ne!(first[0].scalars, first[1].scalars);
+
+        // Generating a single instance, sequentially, gives the same result as generating the set.
+        let single = Instance::generate_seeded(1 << 4, 7, 1);
+        assert_eq!(single.points, first[1].points);
+        assert_eq!(single.scalars, first[1].scalars);
+    }
+
+    #[test]
+    fn checked_deserialization_rejects_invalid_inputs() -> Result<(), Error> {
+        let serialize = |instances: &[Instance]| -> Result<Vec<u8>, Error> {
+            let mut bytes = Vec::new();
+            instances.serialize_unchecked(&mut bytes)?;
+            Ok(bytes)
+        };
+
+        let mut instances = generate_instances(2, 1 << 4, DEFAULT_SEED);
+        let valid = serialize(&instances)?;
+        assert_eq!(
+            deserialize_instances(valid.as_slice(), Validation::Checked)?.len(),
+            2
+        );
+
+        // A truncated input is an error rather than a panic.
+        let truncated = &valid[..valid.len() / 2];
+        assert!(deserialize_instances(truncated, Validation::Checked).is_err());
+        assert!(deserialize_instances(truncated, Validation::Unchecked).is_err());
+
+        // Swapping the coordinates of a point takes it off the curve.
+        let point = instances[1].points[3];
+        instances[1].points[3] = G1Affine::new_unchecked(point.y, point.x);
+        let off_curve = serialize(&instances)?;
+        assert!(deserialize_instances(off_curve.as_slice(), Validation::Unchecked).is_ok());
+        match deserialize_instances(off_curve.as_slice(), Validation::Checked) {
+            Err(Error::InvalidInstance { index: 1, source }) => {
+                assert!(matches!(*source, Error::PointNotOnCurve { index: 3 }))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        instances[1].points[3] = point;
+        instances[0].scalars[5] = BigInt::new([u64::MAX; 4]);
+        let out_of_range = serialize(&instances)?;
+        match deserialize_instances(out_of_range.as_slice(), Validation::Checked) {
+            Err(Error::InvalidInstance { index: 0, source }) => {
+                assert!(matches!(*source, Error::ScalarOutOfRange { index: 5 }))
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn header_is_validated_and_legacy_files_are_accepted() -> Result<(), Error> {
+        let instances = generate_instances(3, 1 << 4, DEFAULT_SEED);
+
+        // Files consisting of a bare serialized vector predate the header.
+        let mut legacy = Vec::new();
+        instances.serialize_unchecked(&mut legacy)?;
+        let read = deserialize_instances(legacy.as_slice(), Validation::Checked)?;
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[2].points, instances[2].points);
+
+        let mut current = Vec::new();
+        serialize_instances(&mut current, &instances, Encoding::Uncompressed)?;
+        match Preamble::read(current.as_slice())? {
+            Preamble::Header(header) => {
+                assert_eq!(header.count, 3);
+                assert_eq!(header.size, Some(1 << 4));
+            }
+            preamble => panic!("unexpected preamble: {:?}", preamble),
+        }
+        assert_eq!(
+            deserialize_instances(current.as_slice(), Validation::Checked)?.len(),
+            3
+        );
+
+        let rewrite_header = |header: Header| -> Result<Vec<u8>, Error> {
+            let mut bytes = Vec::new();
+            header.write(&mut bytes)?;
+            bytes.extend_from_slice(&current[bytes.len()..]);
+            Ok(bytes)
+        };
+        let header = Header::for_instances(&instances, Encoding::Uncompressed);
+
+        let other_curve = rewrite_header(Header { curve: 7, ..header })?;
+        assert!(matches!(
+            deserialize_instances(other_curve.as_slice(), Validation::Checked),
+            Err(Error::CurveMismatch { found: 7, .. })
+        ));
+
+        let future_version = rewrite_header(Header {
+            version: format::FORMAT_VERSION + 1,
+            ..header
+        })?;
+        assert!(matches!(
+            deserialize_instances(future_version.as_slice(), Validation::Checked),
+            Err(Error::UnsupportedVersion(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn appended_instances_are_all_read() -> Result<(), Error> {
+        let path = Path::new(TEST_DIR_BASE).join("append").join("instances");
+        let instances = generate_instances(6, 1 << 4, DEFAULT_SEED);
+
+        write_instances(&path, &instances[..2], false)?;
+        write_instances(&path, &instances[2..5], true)?;
+        write_instances(&path, &instances[5..], true)?;
+        assert_eq!(hash(&read_instances(&path)?)?, hash(&instances)?);
+
+        // Reading one instance at a time gives the same instances, in order.
+        let mut reader = open_instances(&path, Validation::Checked)?;
+        assert_eq!(reader.header().map(|h| h.count), Some(6));
+        let first = reader.next().unwrap()?;
+        assert_eq!(first.points, instances[0].points);
+        assert_eq!(reader.remaining(), 5);
+        assert_eq!(reader.count(), 5);
+
+        // Appending to a legacy file converts it to the current format.
+        let mut legacy = File::create(&path)?;
+        instances[..3].serialize_unchecked(&mut legacy)?;
+        drop(legacy);
+        write_instances(&path, &instances[3..], true)?;
+        assert_eq!(hash(&read_instances(&path)?)?, hash(&instances)?);
+        match open_instances(&path, Validation::Checked)?.header() {
+            Some(header) => assert_eq!(header.version, format::FORMAT_VERSION),
+            None => panic!("expected a header"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn generated_instances_are_written_as_one_file() -> Result<(), Error> {
+        let path = Path::new(TEST_DIR_BASE).join("generated").join("instances");
+        let config = GeneratorConfig::default();
+        let instances = generate_instances_with_config(4, 1 << 4, &config, DEFAULT_SEED);
+        write_instances(&path, &instances[..1], false)?;
+        record_expected_results(&path)?;
+
+        for encoding in [Encoding::Uncompressed, Encoding::Compressed] {
+            write_generated_instances(&path, 4, 1 << 4, &config, DEFAULT_SEED, encoding)?;
+            assert_eq!(read_expected_results(&path)?, None);
+            let mut expected = Vec::new();
+            serialize_instances(&mut expected, &instances, encoding)?;
+            assert_eq!(std::fs::read(&path)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_decoding_matches_reading() -> Result<(), Error> {
+        let instances = generate_instances(3, 1 << 4, DEFAULT_SEED);
+        let mut current = Vec::new();
+        serialize_instances(&mut current, &instances, Encoding::Uncompressed)?;
+        let mut legacy = Vec::new();
+        instances.serialize_unchecked(&mut legacy)?;
+
+        for bytes in [&current, &legacy] {
+            let mut decoder = InstanceDecoder::new(Validation::Checked);
+            let mut decoded = Vec::new();
+            for chunk in bytes.chunks(100) {
+                assert!(decoder.finish().is_err());
+                decoder.feed(chunk);
+                while let Some(instance) = decoder.next_instance()? {
+                    decoded.push(instance);
+                }
+            }
+            decoder.finish()?;
+            assert_eq!(hash(&decoded)?, hash(&instances)?);
+            assert_eq!(
+                hash_instances(InstanceReader::new(bytes.as_slice(), Validation::Checked)?)?,
+                hash(&instances)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_and_uncompressed_files_hold_the_same_instances() -> Result<(), Error> {
+        let dir = Path::new(TEST_DIR_BASE).join("encoding");
+        let (compressed, uncompressed) = (dir.join("compressed"), dir.join("uncompressed"));
+        let mut instances = generate_instances(2, 1 << 6, DEFAULT_SEED);
+        instances[1].points[0] = G1Affine::identity();
+        instances[1].points[1] = -instances[1].points[2];
+
+        write_instances_with_encoding(&compressed, &instances[..1], false, Encoding::Compressed)?;
+        write_instances_with_encoding(&compressed, &instances[1..], true, Encoding::Compressed)?;
+        write_instances(&uncompressed, &instances, false)?;
+        assert!(matches!(
+            write_instances(&compressed, &instances, true),
+            Err(Error::EncodingMismatch { .. })
+        ));
+
+        let compressed_len = compressed.metadata()?.len();
+        assert!(compressed_len < uncompressed.metadata()?.len() * 3 / 4);
+        assert_eq!(
+            hash_instances(open_instances(&compressed, Validation::Checked)?)?,
+            hash(&instances)?
+        );
+        assert_eq!(
+            hash_instances(open_instances(&uncompressed, Validation::Checked)?)?,
+            hash(&instances)?
+        );
+
+        // Chunked decoding handles compressed frames too.
+        let bytes = std::fs::read(&compressed)?;
+        let mut decoder = InstanceDecoder::new(Validation::Checked);
+        decoder.feed(&bytes);
+        let mut decoded = Vec::new();
+        while let Some(instance) = decoder.next_instance()? {
+            decoded.push(instance);
+        }
+        decoder.finish()?;
+        assert_eq!(hash(&decoded)?, hash(&instances)?);
+        Ok(())
+    }
+
     #[test]
     fn serialization_derserialization_are_consistent() -> Result<(), Error> {
         let serialize_hash = {
@@ -246,4 +1137,58 @@ mod test {
         assert_eq!(serialize_hash, deserialize_hash);
         Ok(())
     }
+
+    #[test]
+    fn verification_reports_mismatches() -> Result<(), Error> {
+        let path = Path::new(TEST_DIR_BASE).join("verify").join("instances");
+        let instances = generate_instances(3, 1 << 5, DEFAULT_SEED);
+        write_instances(&path, &instances, false)?;
+
+        // Without a sidecar, results are compared against the baseline.
+        let report = verify_instances(&path, |instance| {
+            instance.compute_msm::<true, true, false>()
+        })?;
+        assert_eq!(report.checked, 3);
+        assert!(report.is_ok());
+
+        record_expected_results(&path)?;
+        let expected = read_expected_results(&path)?.unwrap();
+        assert_eq!(expected.len(), 3);
+        assert_eq!(
+            expected[1],
+            instances[1].compute_msm_baseline().into_affine()
+        );
+
+        // An implementation that is wrong for one instance is caught.
+        let report = verify_instances(&path, |instance| {
+            let mut result = instance.compute_msm::<false, false, false>();
+            if instance.points == instances[2].points {
+                result.double_in_place();
+            }
+            result
+        })?;
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].index, 2);
+        assert_eq!(report.mismatches[0].expected, expected[2]);
+
+        // Writing to the file removes the sidecar, which no longer matches it.
+        write_instances(&path, &instances[..1], true)?;
+        assert_eq!(read_expected_results(&path)?, None);
+        let report = verify_instances(&path, |instance| instance.compute_msm_baseline())?;
+        assert_eq!(report.checked, 4);
+        assert!(report.is_ok());
+
+        // A stale sidecar left by other tools is detected rather than compared against the wrong
+        // instances.
+        expected.serialize(File::create(expected_results_path(&path))?)?;
+        assert!(matches!(
+            verify_instances(&path, |instance| instance.compute_msm_baseline()),
+            Err(Error::CountMismatch {
+                expected: 4,
+                found: 3
+            })
+        ));
+        Ok(())
+    }
 }
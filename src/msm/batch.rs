@@ -0,0 +1,121 @@
+//! Several MSMs over the same points, computed together.
+//!
+//! For each window, the points of every MSM are sorted into one shared list of buckets, which are
+//! then summed with the batch-affine accumulation of [super::pippenger]. The additions of a round,
+//! across all buckets of all MSMs, share a single batch inversion, so the more MSMs there are, the
+//! cheaper each addition becomes.
+
+use super::pippenger::{
+    combine_windows, default_window, reduce_buckets, sum_buckets, window_count, MAX_WINDOW,
+};
+use super::{scalar_window, BigInt, G1Affine, G1Projective};
+use ark_std::cfg_into_iter;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Compute the MSM of `points` with each of the scalar vectors, with windows of `c` bits or the
+/// default for the number of points. Every scalar vector must have one scalar per point.
+pub fn compute_msm_batch<S>(
+    points: &[G1Affine],
+    scalar_vecs: &[S],
+    c: Option<u32>,
+) -> Vec<G1Projective>
+where
+    S: AsRef<[BigInt]> + Sync,
+{
+    for scalars in scalar_vecs {
+        assert_eq!(
+            scalars.as_ref().len(),
+            points.len(),
+            "points and scalars differ in length"
+        );
+    }
+    let c = c.unwrap_or_else(|| default_window(points.len()));
+    assert!(
+        (1..=MAX_WINDOW).contains(&c),
+        "window size must be between 1 and {}",
+        MAX_WINDOW
+    );
+
+    // window_sums[window][msm] holds the sum of the window for that MSM.
+    let window_sums = cfg_into_iter!(0..window_count(c))
+        .map(|window| batch_window_sums(points, scalar_vecs, window, c))
+        .collect::<Vec<_>>();
+    (0..scalar_vecs.len())
+        .map(|msm| {
+            let sums = window_sums.iter().map(|sums| sums[msm]).collect::<Vec<_>>();
+            combine_windows(&sums, c)
+        })
+        .collect()
+}
+
+/// The sum of one window for each MSM.
+fn batch_window_sums<S: AsRef<[BigInt]>>(
+    points: &[G1Affine],
+    scalar_vecs: &[S],
+    window: u32,
+    c: u32,
+) -> Vec<G1Projective> {
+    let buckets_per_msm = (1 << c) - 1;
+    let bucket_count = scalar_vecs.len() * buckets_per_msm;
+
+    // Counting sort of the points of every MSM by bucket. Zero digits go in no bucket.
+    let mut buckets = Vec::with_capacity(points.len() * scalar_vecs.len());
+    let mut offsets = vec![0; bucket_count + 1];
+    for (msm, scalars) in scalar_vecs.iter().enumerate() {
+        for scalar in scalars.as_ref() {
+            let digit = scalar_window(scalar, window * c, c);
+            let bucket = (digit != 0).then(|| msm * buckets_per_msm + digit - 1);
+            if let Some(bucket) = bucket {
+                offsets[bucket + 1] += 1;
+            }
+            buckets.push(bucket);
+        }
+    }
+    for i in 1..offsets.len() {
+        offsets[i] += offsets[i - 1];
+    }
+    let mut sorted = vec![G1Affine::identity(); offsets[bucket_count]];
+    let mut next = offsets.clone();
+    for (i, bucket) in buckets.into_iter().enumerate() {
+        if let Some(bucket) = bucket {
+            sorted[next[bucket]] = points[i % points.len()];
+            next[bucket] += 1;
+        }
+    }
+
+    sum_buckets(sorted, offsets)
+        .chunks(buckets_per_msm)
+        .map(reduce_buckets)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{compute_msm_baseline, mixed_inputs, test_inputs};
+
+    #[test]
+    fn batch_matches_individual_msms() {
+        let (points, _) = mixed_inputs(150);
+        // Scalar families that fill single buckets with many copies of the same point, so that
+        // doublings and cancellations are exercised as well as plain additions.
+        let scalar_vecs = ["uniform", "mixed", "all-equal", "minus-one", "window:4"]
+            .into_iter()
+            .map(|scalars| test_inputs(150, scalars, "mixed").1)
+            .collect::<Vec<_>>();
+
+        let expected = scalar_vecs
+            .iter()
+            .map(|scalars| compute_msm_baseline(&points, scalars))
+            .collect::<Vec<_>>();
+        for c in [None, Some(1), Some(4), Some(10)] {
+            assert_eq!(compute_msm_batch(&points, &scalar_vecs, c), expected);
+            assert_eq!(
+                compute_msm_batch(&points, &scalar_vecs[2..3], c),
+                expected[2..3]
+            );
+        }
+        assert!(compute_msm_batch::<Vec<BigInt>>(&points, &[], None).is_empty());
+    }
+}
@@ -0,0 +1,415 @@
+//! Distributions of generated inputs, used to exercise edge cases of the MSM implementations.
+//!
+//! Uniformly random inputs almost never hit the corner cases of the bucket method, such as empty
+//! buckets, all points landing in one bucket, or window digits at their extremes. The families here
+//! produce such inputs on purpose, so that implementations can be checked against the baseline on
+//! them.
+
+use super::{BigInt, Error, G1Affine, ScalarField};
+use ark_ff::{BigInteger, One, PrimeField, UniformRand, Zero};
+use ark_std::rand::Rng;
+use ark_std::{fmt, str::FromStr};
+
+/// Number of high bits in which [ScalarDistribution::SparseHigh] places its set bits. All positions
+/// are below the top bit of the modulus, so the scalars are always in range.
+const SPARSE_HIGH_BITS: u32 = 64;
+
+/// Maximum number of bits set in a [ScalarDistribution::SparseHigh] scalar.
+const SPARSE_HIGH_WEIGHT: usize = 4;
+
+/// Family of scalars to generate.
+///
+/// Parsed from and displayed as the names accepted by the `--scalars` option of
+/// `generate-input-files`: `uniform`, `zero`, `one`, `minus-one`, `small:<bits>`, `all-equal`,
+/// `sparse-high`, `window:<c>` and `mixed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarDistribution {
+    /// Uniformly random scalars over the whole field.
+    Uniform,
+    /// Every scalar is zero.
+    Zero,
+    /// Every scalar is one.
+    One,
+    /// Every scalar is r - 1, the largest scalar.
+    MinusOne,
+    /// Uniformly random scalars below 2^bits.
+    Small(u32),
+    /// A single random scalar, repeated for every point.
+    AllEqual,
+    /// Scalars with only a few bits set, all of them among the high bits.
+    SparseHigh,
+    /// Scalars whose digits in every window of `c` bits are 0, 1, 2^(c-1) or 2^c - 1.
+    WindowBoundary { c: u32 },
+    /// Each scalar drawn from a randomly chosen family among the others.
+    Mixed,
+}
+
+impl Default for ScalarDistribution {
+    fn default() -> Self {
+        Self::Uniform
+    }
+}
+
+impl ScalarDistribution {
+    /// Draw `size` scalars from the distribution.
+    ///
+    /// [ScalarDistribution::Uniform] draws exactly one field element per scalar, so it gives the
+    /// same scalars as the generator did before distributions were introduced.
+    pub fn sample<R: Rng + ?Sized>(&self, size: usize, rng: &mut R) -> Vec<BigInt> {
+        match self {
+            Self::AllEqual => vec![ScalarField::rand(rng).into_bigint(); size],
+            _ => (0..size).map(|_| self.sample_one(rng)).collect(),
+        }
+    }
+
+    fn sample_one<R: Rng + ?Sized>(&self, rng: &mut R) -> BigInt {
+        let bits = ScalarField::MODULUS_BIT_SIZE;
+        match *self {
+            Self::Uniform | Self::AllEqual => ScalarField::rand(rng).into_bigint(),
+            Self::Zero => ScalarField::zero().into_bigint(),
+            Self::One => ScalarField::one().into_bigint(),
+            Self::MinusOne => (-ScalarField::one()).into_bigint(),
+            Self::Small(width) => from_bits(width.min(bits - 1), |_| rng.gen()),
+            Self::SparseHigh => {
+                let low = bits - 1 - SPARSE_HIGH_BITS;
+                let set = (0..rng.gen_range(1..=SPARSE_HIGH_WEIGHT))
+                    .map(|_| rng.gen_range(low..bits - 1))
+                    .collect::<Vec<_>>();
+                from_bits(bits - 1, |i| set.contains(&i))
+            }
+            Self::WindowBoundary { c } => {
+                let top = 1u64 << (c - 1);
+                let digits = (0..(bits + c - 1) / c)
+                    .map(|_| [0, 1, top, (top << 1) - 1][rng.gen_range(0..4)])
+                    .collect::<Vec<u64>>();
+                // Truncating below the top bit of the modulus keeps the scalar in range, at the
+                // cost of clipping the most significant window.
+                from_bits(bits - 1, |i| (digits[(i / c) as usize] >> (i % c)) & 1 == 1)
+            }
+            Self::Mixed => {
+                let family = match rng.gen_range(0..8) {
+                    0 => Self::Uniform,
+                    1 => Self::Zero,
+                    2 => Self::One,
+                    3 => Self::MinusOne,
+                    4 => Self::Small(16),
+                    5 => Self::Small(64),
+                    6 => Self::SparseHigh,
+                    _ => Self::WindowBoundary {
+                        c: rng.gen_range(2..=16),
+                    },
+                };
+                family.sample_one(rng)
+            }
+        }
+    }
+}
+
+/// Construct a scalar from its `len` low bits, with bit `i` given by `bit(i)`.
+fn from_bits(len: u32, bit: impl FnMut(u32) -> bool) -> BigInt {
+    BigInt::from_bits_le(&(0..len).map(bit).collect::<Vec<_>>())
+}
+
+impl FromStr for ScalarDistribution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidDistribution(s.to_owned());
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg.parse::<u32>().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let distribution = match (name, arg) {
+            ("uniform", None) => Self::Uniform,
+            ("zero", None) => Self::Zero,
+            ("one", None) => Self::One,
+            ("minus-one", None) => Self::MinusOne,
+            ("small", Some(bits)) if (1..ScalarField::MODULUS_BIT_SIZE).contains(&bits) => {
+                Self::Small(bits)
+            }
+            ("all-equal", None) => Self::AllEqual,
+            ("sparse-high", None) => Self::SparseHigh,
+            ("window", Some(c)) if (1..=32).contains(&c) => Self::WindowBoundary { c },
+            ("mixed", None) => Self::Mixed,
+            _ => return Err(invalid()),
+        };
+        Ok(distribution)
+    }
+}
+
+impl fmt::Display for ScalarDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Uniform => write!(f, "uniform"),
+            Self::Zero => write!(f, "zero"),
+            Self::One => write!(f, "one"),
+            Self::MinusOne => write!(f, "minus-one"),
+            Self::Small(bits) => write!(f, "small:{}", bits),
+            Self::AllEqual => write!(f, "all-equal"),
+            Self::SparseHigh => write!(f, "sparse-high"),
+            Self::WindowBoundary { c } => write!(f, "window:{}", c),
+            Self::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+/// Family of points to generate.
+///
+/// Every family starts from random multiples of the generator and then rewrites some of them,
+/// creating the cases where batched affine addition must special-case doubling and inverses.
+/// Parsed from and displayed as the names accepted by the `--points` option of
+/// `generate-input-files`: `random`, `duplicates`, `negations`, `with-infinity`,
+/// `bucket-collisions` and `mixed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointDistribution {
+    /// Random multiples of the generator.
+    Random,
+    /// About half of the points repeat an earlier point.
+    Duplicates,
+    /// About half of the points are the negation of an earlier point.
+    Negations,
+    /// About a quarter of the points, including the first, are the point at infinity.
+    WithInfinity,
+    /// Points come in pairs of P and either P or -P, with both given the same scalar, so that the
+    /// pair lands in the same bucket in every window.
+    BucketCollisions,
+    /// Each point rewritten as in a randomly chosen family among the others, or kept.
+    Mixed,
+}
+
+impl Default for PointDistribution {
+    fn default() -> Self {
+        Self::Random
+    }
+}
+
+/// Rewrite applied to a single point by a [PointDistribution].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PointAction {
+    Keep,
+    Duplicate,
+    Negate,
+    Infinity,
+    Collide,
+}
+
+impl PointDistribution {
+    /// Rewrite random points, and for [PointDistribution::BucketCollisions] their scalars, to
+    /// follow the distribution.
+    ///
+    /// [PointDistribution::Random] leaves the inputs untouched and draws nothing from the RNG, so
+    /// it gives the same inputs as the generator did before distributions were introduced.
+    pub fn apply<R: Rng + ?Sized>(
+        &self,
+        points: &mut [G1Affine],
+        scalars: &mut [BigInt],
+        rng: &mut R,
+    ) {
+        if *self == Self::Random {
+            return;
+        }
+        for i in 0..points.len() {
+            let action = match self {
+                Self::Duplicates if rng.gen_bool(0.5) => PointAction::Duplicate,
+                Self::Negations if rng.gen_bool(0.5) => PointAction::Negate,
+                Self::WithInfinity if i == 0 || rng.gen_bool(0.25) => PointAction::Infinity,
+                Self::BucketCollisions if i % 2 == 1 => PointAction::Collide,
+                Self::Mixed => [
+                    PointAction::Keep,
+                    PointAction::Duplicate,
+                    PointAction::Negate,
+                    PointAction::Infinity,
+                    PointAction::Collide,
+                ][rng.gen_range(0..5)],
+                _ => PointAction::Keep,
+            };
+            match action {
+                PointAction::Keep => (),
+                // The first point has no earlier point to copy.
+                _ if i == 0 && action != PointAction::Infinity => (),
+                PointAction::Duplicate => points[i] = points[rng.gen_range(0..i)],
+                PointAction::Negate => points[i] = -points[rng.gen_range(0..i)],
+                PointAction::Infinity => points[i] = G1Affine::identity(),
+                PointAction::Collide => {
+                    points[i] = if rng.gen() {
+                        points[i - 1]
+                    } else {
+                        -points[i - 1]
+                    };
+                    scalars[i] = scalars[i - 1];
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for PointDistribution {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "random" => Ok(Self::Random),
+            "duplicates" => Ok(Self::Duplicates),
+            "negations" => Ok(Self::Negations),
+            "with-infinity" => Ok(Self::WithInfinity),
+            "bucket-collisions" => Ok(Self::BucketCollisions),
+            "mixed" => Ok(Self::Mixed),
+            _ => Err(Error::InvalidDistribution(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for PointDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Random => write!(f, "random"),
+            Self::Duplicates => write!(f, "duplicates"),
+            Self::Negations => write!(f, "negations"),
+            Self::WithInfinity => write!(f, "with-infinity"),
+            Self::BucketCollisions => write!(f, "bucket-collisions"),
+            Self::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+/// Options controlling the inputs produced by the generator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GeneratorConfig {
+    pub scalars: ScalarDistribution,
+    pub points: PointDistribution,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{
+        compute_msm, compute_msm_baseline, generate_msm_inputs_with_config, instance_rng,
+        test_inputs, DEFAULT_SEED,
+    };
+
+    const SCALAR_DISTRIBUTIONS: [&str; 11] = [
+        "uniform",
+        "zero",
+        "one",
+        "minus-one",
+        "small:16",
+        "small:64",
+        "all-equal",
+        "sparse-high",
+        "window:8",
+        "window:13",
+        "mixed",
+    ];
+
+    const POINT_DISTRIBUTIONS: [&str; 6] = [
+        "random",
+        "duplicates",
+        "negations",
+        "with-infinity",
+        "bucket-collisions",
+        "mixed",
+    ];
+
+    #[test]
+    fn names_round_trip() -> Result<(), Error> {
+        for name in SCALAR_DISTRIBUTIONS {
+            assert_eq!(name.parse::<ScalarDistribution>()?.to_string(), name);
+        }
+        for name in ["", "small", "small:0", "small:x", "window:0", "uniform:3"] {
+            assert!(name.parse::<ScalarDistribution>().is_err());
+        }
+        for name in POINT_DISTRIBUTIONS {
+            assert_eq!(name.parse::<PointDistribution>()?.to_string(), name);
+        }
+        assert!("infinity".parse::<PointDistribution>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn uniform_draws_one_field_element_per_scalar() {
+        let mut rng = instance_rng(DEFAULT_SEED, 0);
+        let sampled = ScalarDistribution::Uniform.sample(1 << 4, &mut rng);
+        let mut rng = instance_rng(DEFAULT_SEED, 0);
+        let expected = (0..1 << 4)
+            .map(|_| ScalarField::rand(&mut rng).into_bigint())
+            .collect::<Vec<_>>();
+        assert_eq!(sampled, expected);
+    }
+
+    #[test]
+    fn compute_msm_matches_baseline_on_all_scalar_distributions() {
+        for name in SCALAR_DISTRIBUTIONS {
+            let (points, scalars) = test_inputs(1 << 8, name, "random");
+            for scalar in scalars.iter() {
+                assert!(ScalarField::from_bigint(*scalar).is_some(), "{}", name);
+            }
+
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for c in [None, Some(4), Some(8), Some(13)] {
+                assert_eq!(
+                    compute_msm::<true, false, false>(&points, &scalars, c),
+                    baseline,
+                    "{} with c = {:?}",
+                    name,
+                    c
+                );
+                assert_eq!(
+                    compute_msm::<true, true, false>(&points, &scalars, c),
+                    baseline,
+                    "{} with c = {:?}",
+                    name,
+                    c
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compute_msm_matches_baseline_on_all_point_distributions() {
+        for (points, scalars) in POINT_DISTRIBUTIONS
+            .iter()
+            .flat_map(|p| ["uniform", "small:16"].map(|s| (*p, s)))
+        {
+            let name = format!("{} points with {} scalars", points, scalars);
+            let (points, scalars) = test_inputs(1 << 8, scalars, points);
+
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for c in [None, Some(4), Some(8)] {
+                let results = [
+                    compute_msm::<true, true, false>(&points, &scalars, c),
+                    compute_msm::<true, false, false>(&points, &scalars, c),
+                    compute_msm::<false, true, false>(&points, &scalars, c),
+                    compute_msm::<false, false, false>(&points, &scalars, c),
+                ];
+                for result in results {
+                    assert_eq!(result, baseline, "{} with c = {:?}", name, c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn point_distributions_produce_their_special_cases() {
+        let sample = |points: PointDistribution| {
+            let config = GeneratorConfig {
+                points,
+                ..Default::default()
+            };
+            generate_msm_inputs_with_config(1 << 6, &config, &mut instance_rng(DEFAULT_SEED, 0))
+        };
+
+        let (random, _) = sample(PointDistribution::Random);
+        let (points, _) = sample(PointDistribution::Duplicates);
+        assert!((1..points.len()).any(|i| points[..i].contains(&points[i])));
+        let (points, _) = sample(PointDistribution::Negations);
+        assert!((1..points.len()).any(|i| points[..i].contains(&-points[i])));
+        let (points, _) = sample(PointDistribution::WithInfinity);
+        assert!(points[0].infinity);
+        let (points, scalars) = sample(PointDistribution::BucketCollisions);
+        for i in (1..points.len()).step_by(2) {
+            assert!(points[i] == points[i - 1] || points[i] == -points[i - 1]);
+            assert_eq!(scalars[i], scalars[i - 1]);
+        }
+        assert_eq!(points[0], random[0]);
+    }
+}
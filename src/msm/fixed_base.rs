@@ -0,0 +1,179 @@
+//! Fixed-base scalar multiplication with precomputed windowed tables.
+//!
+//! When many multiples of the same base are needed, as when generating instances, precomputing
+//! `d · 2^(w·j) · B` for every digit `d` of every `w`-bit window `j` reduces each multiplication to
+//! one mixed addition per window, with no doublings. With the 13-bit window chosen for large
+//! batches, that is 20 mixed additions per scalar, against 255 doublings and about 128 additions
+//! for double-and-add, or about 128 mixed additions with a table of `2^i · B` alone: roughly a 6x
+//! speedup over the latter. The `fixed_base` bench compares the table with double-and-add.
+
+use super::pippenger::MAX_WINDOW;
+use super::{batch_normalize, scalar_window, BigInt, G1Affine, G1Projective, ScalarField};
+use ark_ec::ProjectiveCurve;
+use ark_ff::{PrimeField, Zero};
+use ark_std::cfg_iter;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Largest table, in points, that [FixedBaseTable::window_for] will choose. At about 100 bytes per
+/// point this keeps the table to tens of megabytes, which matters in the browser.
+const MAX_TABLE_LEN: usize = 1 << 18;
+
+/// Precomputed multiples of a fixed base, split by window.
+#[derive(Clone, Debug)]
+pub struct FixedBaseTable {
+    window: u32,
+    /// `tables[j][d]` holds `d · 2^(window·j) · base`.
+    tables: Vec<Vec<G1Affine>>,
+}
+
+impl FixedBaseTable {
+    /// Precompute the table for multiplying `base` by scalars in windows of `window` bits.
+    pub fn new(base: G1Projective, window: u32) -> Self {
+        assert!(
+            (1..=MAX_WINDOW).contains(&window),
+            "window must be between 1 and {}",
+            MAX_WINDOW
+        );
+        let windows = Self::window_count(window);
+        let digits = 1 << window;
+
+        // The base of window j is 2^(window·j) times the base.
+        let mut window_bases = Vec::with_capacity(windows);
+        let mut window_base = base;
+        for _ in 0..windows {
+            window_bases.push(window_base);
+            for _ in 0..window {
+                window_base.double_in_place();
+            }
+        }
+
+        let multiples = cfg_iter!(window_bases)
+            .flat_map(|window_base| {
+                let mut multiple = G1Projective::zero();
+                (0..digits)
+                    .map(|_| {
+                        let current = multiple;
+                        multiple += window_base;
+                        current
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let tables = batch_normalize(&multiples)
+            .chunks(digits)
+            .map(|table| table.to_vec())
+            .collect();
+        Self { window, tables }
+    }
+
+    /// Choose the window that minimizes the total number of additions for `count` multiplications,
+    /// counting both the precomputation and the multiplications themselves, among those whose
+    /// table stays within a reasonable amount of memory.
+    pub fn window_for(count: usize) -> u32 {
+        (1..=MAX_WINDOW)
+            .filter(|&window| Self::window_count(window) << window <= MAX_TABLE_LEN)
+            .min_by_key(|&window| {
+                let windows = Self::window_count(window) as u64;
+                windows * (1 << window) + windows * count as u64
+            })
+            .unwrap()
+    }
+
+    fn window_count(window: u32) -> usize {
+        ((ScalarField::MODULUS_BIT_SIZE + window - 1) / window) as usize
+    }
+
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// Multiply the base by the given scalar.
+    pub fn mul(&self, scalar: &BigInt) -> G1Projective {
+        let mut result = G1Projective::zero();
+        for (j, table) in self.tables.iter().enumerate() {
+            let digit = scalar_window(scalar, j as u32 * self.window, self.window);
+            if digit != 0 {
+                result.add_assign_mixed(&table[digit]);
+            }
+        }
+        result
+    }
+
+    /// Multiply the base by each of the given scalars, normalizing the results together.
+    pub fn batch_mul(&self, scalars: &[BigInt]) -> Vec<G1Affine> {
+        let results = cfg_iter!(scalars).map(|s| self.mul(s)).collect::<Vec<_>>();
+        batch_normalize(&results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{instance_rng, DEFAULT_SEED};
+    use ark_ff::{fields::BitIteratorLE, One, UniformRand};
+
+    fn naive_mul(base: G1Projective, scalar: &BigInt) -> G1Projective {
+        let mut result = G1Projective::zero();
+        for bit in BitIteratorLE::new(scalar)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+        {
+            result.double_in_place();
+            if bit {
+                result += &base;
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn table_matches_double_and_add() {
+        let mut rng = instance_rng(DEFAULT_SEED, 0);
+        let base = naive_mul(
+            G1Projective::prime_subgroup_generator(),
+            &BigInt::from(7u64),
+        );
+        let mut scalars = (0..32)
+            .map(|_| ScalarField::rand(&mut rng).into_bigint())
+            .collect::<Vec<_>>();
+        scalars.push(ScalarField::zero().into_bigint());
+        scalars.push(ScalarField::one().into_bigint());
+        scalars.push((-ScalarField::one()).into_bigint());
+
+        let expected = scalars
+            .iter()
+            .map(|s| naive_mul(base, s))
+            .collect::<Vec<_>>();
+        let expected = G1Projective::batch_normalization_into_affine(&expected);
+        for window in [1, 4, 8, 13] {
+            let table = FixedBaseTable::new(base, window);
+            assert_eq!(table.batch_mul(&scalars), expected, "window {}", window);
+        }
+    }
+
+    #[test]
+    fn batch_mul_matches_individual_multiplications() {
+        let mut rng = instance_rng(DEFAULT_SEED, 1);
+        // Enough scalars that the work is split into several chunks with the parallel feature.
+        let scalars = (0..10_000)
+            .map(|_| ScalarField::rand(&mut rng).into_bigint())
+            .collect::<Vec<_>>();
+        let table = FixedBaseTable::new(G1Projective::prime_subgroup_generator(), 8);
+        let expected = scalars
+            .iter()
+            .map(|s| table.mul(s).into_affine())
+            .collect::<Vec<_>>();
+        assert_eq!(table.batch_mul(&scalars), expected);
+    }
+
+    #[test]
+    fn window_grows_with_count() {
+        assert!(FixedBaseTable::window_for(1) <= FixedBaseTable::window_for(1 << 10));
+        assert!(FixedBaseTable::window_for(1 << 10) <= FixedBaseTable::window_for(1 << 20));
+        let window = FixedBaseTable::window_for(1 << 20);
+        assert!(FixedBaseTable::window_count(window) << window <= MAX_TABLE_LEN);
+    }
+}
@@ -0,0 +1,510 @@
+//! Container format for instance files.
+//!
+//! Instance files start with a fixed-size header identifying the format, its version and the
+//! curve, followed by a sequence of frames, each holding one length-prefixed serialized instance.
+//! Appending writes new frames at the end of the file and then rewrites the header, so the
+//! instance count in the header only covers frames that were completely written.
+//!
+//! Two older layouts are still accepted by the reader: version 1 files, with a header followed by
+//! a serialized `Vec<Instance>`, and legacy files, consisting of only the serialized vector.
+
+use super::{BigInt, Encoding, Error, G1Affine, Instance, Validation};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError, Write};
+
+/// Magic bytes at the start of every instance file. Interpreted as the length prefix of a legacy
+/// headerless file, this is far larger than any real instance count, so the two cannot be confused.
+pub const MAGIC: [u8; 8] = *b"\x89ZKMSM\r\n";
+
+/// Current version of the instance file format.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Identifier for BLS12-381 G1, the only curve supported by this crate.
+pub const CURVE_BLS12_381_G1: u16 = 1;
+
+/// Header flag indicating that points are stored in compressed form.
+pub const FLAG_COMPRESSED: u16 = 1 << 0;
+
+/// Flags understood by this version of the crate.
+const SUPPORTED_FLAGS: u16 = FLAG_COMPRESSED;
+
+/// Value of the encoded size field when the instances in a file differ in size.
+const MIXED_SIZES: u64 = u64::MAX;
+
+/// Header written at the start of every instance file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub curve: u16,
+    pub flags: u16,
+    /// Number of instances in the file.
+    pub count: u64,
+    /// Size shared by every instance in the file, or `None` if the sizes differ.
+    pub size: Option<u64>,
+}
+
+impl Header {
+    /// Construct the header describing the given instances in the current format.
+    pub fn for_instances(instances: &[Instance], encoding: Encoding) -> Self {
+        let flags = match encoding {
+            Encoding::Uncompressed => 0,
+            Encoding::Compressed => FLAG_COMPRESSED,
+        };
+        let mut header = Self {
+            version: FORMAT_VERSION,
+            curve: CURVE_BLS12_381_G1,
+            flags,
+            count: 0,
+            size: None,
+        };
+        header.extend(instances);
+        header
+    }
+
+    /// Encoding of the points in the file.
+    pub fn encoding(&self) -> Encoding {
+        if self.flags & FLAG_COMPRESSED != 0 {
+            Encoding::Compressed
+        } else {
+            Encoding::Uncompressed
+        }
+    }
+
+    /// Update the count and size summary to account for instances appended to the file.
+    pub fn extend(&mut self, instances: &[Instance]) {
+        for instance in instances {
+            let size = instance.size() as u64;
+            if self.count == 0 {
+                self.size = Some(size);
+            } else if self.size != Some(size) {
+                self.size = None;
+            }
+            self.count += 1;
+        }
+    }
+
+    /// Check that files with this header can be read by this version of the crate.
+    pub fn check_supported(&self) -> Result<(), Error> {
+        if self.version == 0 || self.version > FORMAT_VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        if self.curve != CURVE_BLS12_381_G1 {
+            return Err(Error::CurveMismatch {
+                expected: CURVE_BLS12_381_G1,
+                found: self.curve,
+            });
+        }
+        // Version 1 did not define any flags.
+        if self.flags & !SUPPORTED_FLAGS != 0 || (self.version == 1 && self.flags != 0) {
+            return Err(Error::UnsupportedFlags(self.flags));
+        }
+        Ok(())
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        u64::from_le_bytes(MAGIC).serialize(&mut writer)?;
+        self.version.serialize(&mut writer)?;
+        self.curve.serialize(&mut writer)?;
+        self.flags.serialize(&mut writer)?;
+        // Reserved for future use.
+        0u16.serialize(&mut writer)?;
+        self.count.serialize(&mut writer)?;
+        self.size.unwrap_or(MIXED_SIZES).serialize(&mut writer)?;
+        Ok(())
+    }
+}
+
+/// Beginning of an instance file: either a header or, in legacy files, the instance count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preamble {
+    Header(Header),
+    Legacy { count: u64 },
+}
+
+impl Preamble {
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let first = u64::deserialize(&mut reader)?;
+        if first != u64::from_le_bytes(MAGIC) {
+            return Ok(Preamble::Legacy { count: first });
+        }
+
+        let version = u16::deserialize(&mut reader)?;
+        let curve = u16::deserialize(&mut reader)?;
+        let flags = u16::deserialize(&mut reader)?;
+        let _reserved = u16::deserialize(&mut reader)?;
+        let count = u64::deserialize(&mut reader)?;
+        let size = match u64::deserialize(&mut reader)? {
+            MIXED_SIZES => None,
+            size => Some(size),
+        };
+
+        Ok(Preamble::Header(Header {
+            version,
+            curve,
+            flags,
+            count,
+            size,
+        }))
+    }
+}
+
+/// Write each instance as a length-prefixed frame.
+pub fn write_frames<W: Write>(
+    mut writer: W,
+    instances: &[Instance],
+    encoding: Encoding,
+) -> Result<(), Error> {
+    let mut frame = Vec::new();
+    for instance in instances {
+        frame.clear();
+        instance.serialize_with_encoding(&mut frame, encoding)?;
+        (frame.len() as u64).serialize(&mut writer)?;
+        writer.write_all(&frame).map_err(SerializationError::from)?;
+    }
+    Ok(())
+}
+
+/// Layout of the instance data following the preamble.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Body {
+    /// Length-prefixed frames, one per instance.
+    Framed,
+    /// Instances serialized back to back, as in a `Vec<Instance>`.
+    Packed,
+}
+
+/// Position within the instances following the preamble.
+#[derive(Clone, Copy, Debug)]
+struct Cursor {
+    header: Option<Header>,
+    body: Body,
+    count: u64,
+    index: u64,
+}
+
+impl Cursor {
+    /// Read the preamble, and for version 1 files the vector length prefix that follows it.
+    fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let (header, body, count) = match Preamble::read(&mut reader)? {
+            Preamble::Header(header) => {
+                header.check_supported()?;
+                if header.version == 1 {
+                    // Version 1 files repeat the count as the length prefix of the vector.
+                    let count = u64::deserialize(&mut reader)?;
+                    if count != header.count {
+                        return Err(Error::CountMismatch {
+                            expected: header.count,
+                            found: count,
+                        });
+                    }
+                    (Some(header), Body::Packed, count)
+                } else {
+                    (Some(header), Body::Framed, header.count)
+                }
+            }
+            Preamble::Legacy { count } => (None, Body::Packed, count),
+        };
+
+        Ok(Self {
+            header,
+            body,
+            count,
+            index: 0,
+        })
+    }
+
+    /// Number of bytes taken up by the preamble at the start of `bytes`, or `None` if more bytes
+    /// are needed to tell.
+    fn preamble_len(bytes: &[u8]) -> Option<usize> {
+        if bytes.len() < MAGIC.len() {
+            return None;
+        }
+        if bytes[..MAGIC.len()] != MAGIC {
+            return Some(MAGIC.len());
+        }
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        match u16::from_le_bytes([bytes[8], bytes[9]]) {
+            1 => Some(HEADER_LEN + 8),
+            _ => Some(HEADER_LEN),
+        }
+    }
+
+    fn remaining(&self) -> u64 {
+        self.count - self.index
+    }
+
+    /// Encoding of the points in the instances. Only files with a header can be compressed.
+    fn encoding(&self) -> Encoding {
+        self.header
+            .map_or(Encoding::Uncompressed, |header| header.encoding())
+    }
+
+    /// Check a decoded instance and advance past it.
+    fn accept(&mut self, instance: &Instance, validation: Validation) -> Result<(), Error> {
+        let index = self.index as usize;
+
+        // Validation is done separately, rather than with the checked deserialization methods, so
+        // that errors identify the offending element and scalars are checked against the modulus.
+        if validation == Validation::Checked {
+            instance.validate().map_err(|e| Error::InvalidInstance {
+                index,
+                source: Box::new(e),
+            })?;
+        }
+        if let Some(expected) = self.header.and_then(|h| h.size) {
+            if instance.size() as u64 != expected {
+                return Err(Error::SizeMismatch {
+                    index,
+                    expected,
+                    found: instance.size() as u64,
+                });
+            }
+        }
+        self.index += 1;
+        Ok(())
+    }
+}
+
+/// Length in bytes of the preamble of a file with a header, excluding any data following it.
+const HEADER_LEN: usize = 32;
+
+/// Iterator reading one instance at a time from an instance file in any supported layout.
+///
+/// Only the instance currently being read is held in memory. Iteration stops after the first
+/// error.
+pub struct InstanceReader<R> {
+    reader: R,
+    validation: Validation,
+    cursor: Cursor,
+}
+
+impl<R: Read> InstanceReader<R> {
+    /// Read the preamble of an instance file and prepare to read the instances that follow it.
+    pub fn new(mut reader: R, validation: Validation) -> Result<Self, Error> {
+        let cursor = Cursor::read(&mut reader)?;
+        Ok(Self {
+            reader,
+            validation,
+            cursor,
+        })
+    }
+
+    /// Header of the file being read, or `None` for legacy headerless files.
+    pub fn header(&self) -> Option<&Header> {
+        self.cursor.header.as_ref()
+    }
+
+    /// Number of instances remaining to be read.
+    pub fn remaining(&self) -> u64 {
+        self.cursor.remaining()
+    }
+
+    fn read_instance(&mut self) -> Result<Instance, Error> {
+        let instance = match self.cursor.body {
+            Body::Framed => {
+                let length = u64::deserialize(&mut self.reader)?;
+                let mut counter = CountingReader::new(&mut self.reader);
+                let instance =
+                    Instance::deserialize_with_encoding(&mut counter, self.cursor.encoding())?;
+                if counter.count != length {
+                    return Err(Error::FrameLengthMismatch {
+                        index: self.cursor.index as usize,
+                        expected: length,
+                        found: counter.count,
+                    });
+                }
+                instance
+            }
+            Body::Packed => Instance::deserialize_unchecked(&mut self.reader)?,
+        };
+        self.cursor.accept(&instance, self.validation)?;
+        Ok(instance)
+    }
+}
+
+impl<R: Read> Iterator for InstanceReader<R> {
+    type Item = Result<Instance, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor.remaining() == 0 {
+            return None;
+        }
+        let result = self.read_instance();
+        if result.is_err() {
+            self.cursor.count = self.cursor.index;
+        }
+        Some(result)
+    }
+}
+
+/// Incremental decoder for instance files that arrive in chunks, such as from a browser stream.
+///
+/// Bytes are only buffered until the instance they belong to is complete, so memory use is bounded
+/// by the size of the largest instance rather than the size of the file.
+pub struct InstanceDecoder {
+    buffer: Vec<u8>,
+    position: usize,
+    validation: Validation,
+    cursor: Option<Cursor>,
+}
+
+impl InstanceDecoder {
+    pub fn new(validation: Validation) -> Self {
+        Self {
+            buffer: Vec::new(),
+            position: 0,
+            validation,
+            cursor: None,
+        }
+    }
+
+    /// Add the next chunk of the file.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        // Drop the bytes of instances that have already been decoded.
+        self.buffer.drain(..self.position);
+        self.position = 0;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Header of the file being decoded, once it has been received. `None` for legacy files.
+    pub fn header(&self) -> Option<&Header> {
+        self.cursor.as_ref().and_then(|c| c.header.as_ref())
+    }
+
+    /// Whether every instance in the file has been decoded.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.cursor, Some(cursor) if cursor.remaining() == 0)
+    }
+
+    /// Check that the file was complete, to be called once all chunks have been fed.
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.is_finished() {
+            return Ok(());
+        }
+        Err(Error::UnexpectedEnd {
+            read: self.cursor.map_or(0, |c| c.index),
+        })
+    }
+
+    /// Decode the next instance if all of its bytes have been received.
+    ///
+    /// Returns `None` if more data is needed, or if every instance has been decoded.
+    pub fn next_instance(&mut self) -> Result<Option<Instance>, Error> {
+        let mut cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => {
+                let bytes = &self.buffer[self.position..];
+                let length = match Cursor::preamble_len(bytes) {
+                    Some(length) if length <= bytes.len() => length,
+                    _ => return Ok(None),
+                };
+                let cursor = Cursor::read(&bytes[..length])?;
+                self.position += length;
+                self.cursor = Some(cursor);
+                cursor
+            }
+        };
+        if cursor.remaining() == 0 {
+            return Ok(None);
+        }
+
+        let bytes = &self.buffer[self.position..];
+        let (start, end) = match Self::instance_bounds(bytes, cursor.body)? {
+            Some((start, end)) if end <= bytes.len() => (start, end),
+            _ => return Ok(None),
+        };
+        let mut data = &bytes[start..end];
+        let instance = Instance::deserialize_with_encoding(&mut data, cursor.encoding())?;
+        if !data.is_empty() {
+            return Err(Error::FrameLengthMismatch {
+                index: cursor.index as usize,
+                expected: (end - start) as u64,
+                found: (end - start - data.len()) as u64,
+            });
+        }
+
+        cursor.accept(&instance, self.validation)?;
+        self.position += end;
+        self.cursor = Some(cursor);
+        Ok(Some(instance))
+    }
+
+    /// Byte range of the serialized instance at the start of `bytes`, or `None` if more bytes are
+    /// needed to tell.
+    fn instance_bounds(bytes: &[u8], body: Body) -> Result<Option<(usize, usize)>, Error> {
+        let read_len = |offset: usize| -> Result<Option<usize>, Error> {
+            match bytes.get(offset..offset + 8) {
+                Some(prefix) => {
+                    let length = u64::deserialize(prefix)?;
+                    usize::try_from(length)
+                        .map(Some)
+                        .map_err(|_| SerializationError::InvalidData.into())
+                }
+                None => Ok(None),
+            }
+        };
+        let overflow = || Error::from(SerializationError::InvalidData);
+
+        match body {
+            Body::Framed => Ok(read_len(0)?
+                .map(|length| length.checked_add(8).ok_or_else(overflow))
+                .transpose()?
+                .map(|end| (8, end))),
+            Body::Packed => {
+                // A packed instance is a vector of points followed by a vector of scalars.
+                let (point_len, scalar_len) = packed_element_lengths();
+                let points = match read_len(0)? {
+                    Some(points) => points,
+                    None => return Ok(None),
+                };
+                let scalars_offset = points
+                    .checked_mul(point_len)
+                    .and_then(|n| n.checked_add(8))
+                    .ok_or_else(overflow)?;
+                let scalars = match read_len(scalars_offset)? {
+                    Some(scalars) => scalars,
+                    None => return Ok(None),
+                };
+                let end = scalars
+                    .checked_mul(scalar_len)
+                    .and_then(|n| n.checked_add(scalars_offset + 8))
+                    .ok_or_else(overflow)?;
+                Ok(Some((0, end)))
+            }
+        }
+    }
+}
+
+/// Serialized lengths of a single point and scalar within an instance.
+fn packed_element_lengths() -> (usize, usize) {
+    let mut point = Vec::new();
+    let mut scalar = Vec::new();
+    G1Affine::identity()
+        .serialize_unchecked(&mut point)
+        .expect("serialization to a vector cannot fail");
+    BigInt::default()
+        .serialize_unchecked(&mut scalar)
+        .expect("serialization to a vector cannot fail");
+    (point.len(), scalar.len())
+}
+
+/// Reader wrapper counting the number of bytes read through it.
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> ark_std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
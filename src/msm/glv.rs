@@ -0,0 +1,171 @@
+//! MSM with the GLV endomorphism of BLS12-381 G1.
+//!
+//! The curve has the endomorphism `φ(x, y) = (β·x, y)`, where `β` is a cube root of unity in the
+//! base field, which acts on the prime-order subgroup as multiplication by `λ = z² - 1`, for the
+//! curve parameter `z = -0xd201000000010000`. As the scalar field modulus is exactly
+//! `λ² + λ + 1`, dividing a scalar `k` by `λ` gives `k = k1 + k2·λ` with both `k1` and `k2` below
+//! 2^128. An MSM of `n` points with 255-bit scalars thus becomes an MSM of the `n` points and their
+//! `n` images under `φ` with 128-bit scalars, which has half as many windows. [super::compute_msm]
+//! does this rewrite when its `GLV` parameter is set, and runs the batch-affine bucket kernel of
+//! [super::pippenger] over the windows below [HALF_BITS] only.
+
+use super::pippenger::{check_window, combine_windows, default_window, window_sums};
+use super::{tuning, BigInt, G1Affine, G1Projective};
+use ark_bls12_381::Fq;
+use ark_ff::PrimeField;
+
+/// `λ = z² - 1`, the eigenvalue of the endomorphism.
+pub const LAMBDA: u128 = 0xac45a4010001a40200000000ffffffff;
+
+/// Big-endian encoding of `β`, the cube root of unity for which `φ` acts as `λ`.
+const BETA: [u8; 48] = [
+    0x1a, 0x01, 0x11, 0xea, 0x39, 0x7f, 0xe6, 0x99, 0xec, 0x02, 0x40, 0x86, 0x63, 0xd4, 0xde, 0x85,
+    0xaa, 0x0d, 0x85, 0x7d, 0x89, 0x75, 0x9a, 0xd4, 0x89, 0x7d, 0x29, 0x65, 0x0f, 0xb8, 0x5f, 0x9b,
+    0x40, 0x94, 0x27, 0xeb, 0x4f, 0x49, 0xff, 0xfd, 0x8b, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xaa, 0xac,
+];
+
+/// Number of bits in each half of a decomposed scalar.
+pub const HALF_BITS: u32 = 128;
+
+/// Apply the endomorphism, giving `λ·point`.
+pub fn endomorphism(point: &G1Affine, beta: &Fq) -> G1Affine {
+    if point.infinity {
+        return *point;
+    }
+    G1Affine::new_unchecked(point.x * beta, point.y)
+}
+
+/// Split a scalar `k` into `(k1, k2)` with `k = k1 + k2·λ`, by long division by `λ`.
+///
+/// Panics if the scalar is not below the scalar field modulus, as the quotient would then not fit
+/// in 128 bits.
+pub fn decompose(scalar: &BigInt) -> (u128, u128) {
+    let mut quotient = 0u128;
+    let mut remainder = 0u128;
+    for i in (0..256).rev() {
+        let bit = (scalar.0[i / 64] >> (i % 64)) & 1;
+        // The remainder is below λ < 2^128, so after shifting it may need one more bit.
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | bit as u128;
+        assert!(
+            quotient >> 127 == 0,
+            "scalar is not below the scalar field modulus"
+        );
+        quotient <<= 1;
+        if carry == 1 || remainder >= LAMBDA {
+            remainder = remainder.wrapping_sub(LAMBDA);
+            quotient |= 1;
+        }
+    }
+    (remainder, quotient)
+}
+
+fn to_bigint(half: u128) -> BigInt {
+    let mut bigint = BigInt::default();
+    bigint.0[0] = half as u64;
+    bigint.0[1] = (half >> 64) as u64;
+    bigint
+}
+
+/// Rewrite an MSM as one over the points and their images under the endomorphism, with the
+/// decomposed halves of the scalars, for [super::compute_msm] with `GLV`.
+pub fn decompose_msm(points: &[G1Affine], scalars: &[BigInt]) -> (Vec<G1Affine>, Vec<BigInt>) {
+    assert_eq!(
+        points.len(),
+        scalars.len(),
+        "points and scalars differ in length"
+    );
+    let beta = Fq::from_be_bytes_mod_order(&BETA);
+    let mut glv_points = Vec::with_capacity(2 * points.len());
+    let mut glv_scalars = Vec::with_capacity(2 * points.len());
+    for (point, scalar) in points.iter().zip(scalars) {
+        let (k1, k2) = decompose(scalar);
+        glv_points.push(*point);
+        glv_scalars.push(to_bigint(k1));
+        glv_points.push(endomorphism(point, &beta));
+        glv_scalars.push(to_bigint(k2));
+    }
+    (glv_points, glv_scalars)
+}
+
+/// Compute an MSM on the decomposed scalars, with windows of `c` bits or the tuned or default
+/// window for twice the size. The halves are below 2^128, so only the windows below [HALF_BITS]
+/// are summed, against 255 bits without the decomposition.
+///
+/// Panics if `c` is not in `1..=MAX_WINDOW`, or if a scalar is not below the scalar field modulus.
+pub fn compute_msm_glv(points: &[G1Affine], scalars: &[BigInt], c: Option<u32>) -> G1Projective {
+    let (points, scalars) = decompose_msm(points, scalars);
+    let c = c
+        .or_else(|| tuning::tuned_window(points.len()))
+        .unwrap_or_else(|| default_window(points.len()));
+    if let Err(err) = check_window(c) {
+        panic!("{}", err);
+    }
+    let windows = (HALF_BITS + c - 1) / c;
+    combine_windows(&window_sums(&points, &scalars, c, 0..windows, false), c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::FixedBaseTable;
+    use crate::msm::{
+        compute_msm, compute_msm_baseline, mixed_inputs, test_inputs, G1Projective, ScalarField,
+    };
+    use ark_ec::ProjectiveCurve;
+    use ark_ff::{One, Zero};
+
+    #[test]
+    fn endomorphism_is_multiplication_by_lambda() {
+        let beta = Fq::from_be_bytes_mod_order(&BETA);
+        assert_eq!(beta * beta * beta, Fq::one());
+        let generator = G1Projective::prime_subgroup_generator();
+        let table = FixedBaseTable::new(generator, 4);
+        assert_eq!(
+            endomorphism(&generator.into_affine(), &beta),
+            table.mul(&to_bigint(LAMBDA)).into_affine()
+        );
+        assert_eq!(
+            endomorphism(&G1Affine::identity(), &beta),
+            G1Affine::identity()
+        );
+    }
+
+    #[test]
+    fn decomposition_recombines() {
+        let lambda = ScalarField::from_bigint(to_bigint(LAMBDA)).unwrap();
+        let (_, mut scalars) = test_inputs(100, "uniform", "random");
+        scalars.push(ScalarField::zero().into_bigint());
+        scalars.push((-ScalarField::one()).into_bigint());
+        scalars.push(to_bigint(LAMBDA));
+        for scalar in scalars {
+            let (k1, k2) = decompose(&scalar);
+            assert!(k1 < LAMBDA);
+            let k1 = ScalarField::from_bigint(to_bigint(k1)).unwrap();
+            let k2 = ScalarField::from_bigint(to_bigint(k2)).unwrap();
+            assert_eq!((k1 + k2 * lambda).into_bigint(), scalar);
+        }
+    }
+
+    #[test]
+    fn glv_matches_baseline() {
+        for size in [0, 1, 100, 1 << 10] {
+            let (points, scalars) = mixed_inputs(size);
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for c in [None, Some(4), Some(11)] {
+                assert_eq!(
+                    compute_msm::<true, true, true>(&points, &scalars, c),
+                    baseline,
+                    "size {}",
+                    size
+                );
+                assert_eq!(
+                    compute_msm::<false, false, true>(&points, &scalars, c),
+                    baseline,
+                    "size {}",
+                    size
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Human-readable JSON representation of instances, intended for debugging and bug reports.
+//!
+//! Each instance is one JSON object, and files hold one object per line (JSON Lines), so that
+//! single instances can be extracted, attached or diffed with standard line-based tools:
+//!
+//! ```json
+//! {"points":[{"x":"0x17f1…c6bb","y":"0x08b3…e7e1"},{"infinity":true}],"scalars":["0x…","0x…"]}
+//! ```
+//!
+//! Coordinates and scalars are big-endian hex with a `0x` prefix, padded to their full width. An
+//! object may also carry the `expected` result of the MSM in the same point representation.
+//!
+//! The conversion is lossless: any instance, including one with invalid points, converts to JSON
+//! and back to an identical instance.
+
+use super::zcash::{read_fq, FQ_LEN, SCALAR_LEN};
+use super::{BigInt, Error, G1Affine, Instance, Validation};
+use ark_ec::AffineCurve;
+use ark_ff::{BigInteger, PrimeField};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// A point with hex-encoded affine coordinates.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JsonPoint {
+    Affine { x: String, y: String },
+    Infinity { infinity: bool },
+}
+
+impl JsonPoint {
+    pub fn from_point(point: &G1Affine) -> Self {
+        if point.infinity {
+            return Self::Infinity { infinity: true };
+        }
+        Self::Affine {
+            x: to_hex(&point.x.into_bigint().to_bytes_be()),
+            y: to_hex(&point.y.into_bigint().to_bytes_be()),
+        }
+    }
+
+    /// Parse the coordinates of the point, which is reported as `index` in errors. Whether the
+    /// point is on the curve is not checked here.
+    pub fn to_point(&self, index: usize) -> Result<G1Affine, Error> {
+        match self {
+            Self::Affine { x, y } => Ok(G1Affine::new_unchecked(
+                read_fq(&from_hex(x, FQ_LEN)?, index)?,
+                read_fq(&from_hex(y, FQ_LEN)?, index)?,
+            )),
+            Self::Infinity { infinity: true } => Ok(G1Affine::identity()),
+            Self::Infinity { infinity: false } => Err(Error::InvalidPointFlags { index }),
+        }
+    }
+}
+
+/// An instance, and optionally its expected result, in JSON form.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JsonInstance {
+    pub points: Vec<JsonPoint>,
+    pub scalars: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<JsonPoint>,
+}
+
+impl JsonInstance {
+    pub fn new(instance: &Instance, expected: Option<&G1Affine>) -> Self {
+        Self {
+            points: instance.points.iter().map(JsonPoint::from_point).collect(),
+            scalars: instance
+                .scalars
+                .iter()
+                .map(|s| to_hex(&s.to_bytes_be()))
+                .collect(),
+            expected: expected.map(JsonPoint::from_point),
+        }
+    }
+
+    /// Convert back into an instance, validating it if requested.
+    pub fn to_instance(&self, validation: Validation) -> Result<Instance, Error> {
+        let points = self
+            .points
+            .iter()
+            .enumerate()
+            .map(|(index, point)| point.to_point(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Scalars are range-checked with the rest of the instance, and only if requested.
+        let scalars = self
+            .scalars
+            .iter()
+            .map(|scalar| Ok(read_raw_scalar(&from_hex(scalar, SCALAR_LEN)?)))
+            .collect::<Result<Vec<BigInt>, Error>>()?;
+        let instance = Instance { points, scalars };
+        if validation == Validation::Checked {
+            instance.validate()?;
+        }
+        Ok(instance)
+    }
+
+    /// Parse the expected result, if one is given, checking that it is on the curve.
+    pub fn expected(&self) -> Result<Option<G1Affine>, Error> {
+        let expected = match &self.expected {
+            Some(expected) => expected.to_point(0),
+            None => return Ok(None),
+        };
+        expected
+            .and_then(|point| {
+                if !point.is_on_curve() {
+                    return Err(Error::PointNotOnCurve { index: 0 });
+                }
+                Ok(Some(point))
+            })
+            .map_err(|e| Error::InvalidExpected(Box::new(e)))
+    }
+}
+
+/// Write an instance as a single line of JSON.
+pub fn write_json_line<W: Write>(
+    mut writer: W,
+    instance: &Instance,
+    expected: Option<&G1Affine>,
+) -> Result<(), Error> {
+    serde_json::to_writer(&mut writer, &JsonInstance::new(instance, expected))?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Read JSON instances, one per line. Blank lines are skipped.
+pub fn read_json_lines<R: BufRead>(reader: R) -> impl Iterator<Item = Result<JsonInstance, Error>> {
+    reader.lines().filter_map(|line| match line {
+        Ok(line) if line.trim().is_empty() => None,
+        Ok(line) => Some(serde_json::from_str(&line).map_err(Error::from)),
+        Err(e) => Some(Err(e.into())),
+    })
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Read a big-endian scalar as is, whether or not it is below the modulus.
+fn read_raw_scalar(bytes: &[u8]) -> BigInt {
+    let mut scalar = BigInt::default();
+    for (limb, chunk) in scalar.0.iter_mut().zip(bytes.rchunks(8)) {
+        let mut limb_bytes = [0; 8];
+        limb_bytes[8 - chunk.len()..].copy_from_slice(chunk);
+        *limb = u64::from_be_bytes(limb_bytes);
+    }
+    scalar
+}
+
+/// Decode a hex value, with or without a `0x` prefix, into `len` big-endian bytes. Values shorter
+/// than the full width are zero-padded.
+fn from_hex(value: &str, len: usize) -> Result<Vec<u8>, Error> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    if digits.len() > 2 * len {
+        return Err(Error::InvalidHex(value.to_owned()));
+    }
+    let padded = format!("{:0>width$}", digits, width = 2 * len);
+    hex::decode(padded).map_err(|_| Error::InvalidHex(value.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{generate_instances, DEFAULT_SEED};
+    use ark_ec::ProjectiveCurve;
+
+    #[test]
+    fn json_round_trip_is_lossless() -> Result<(), Error> {
+        let mut instances = generate_instances(2, 1 << 5, DEFAULT_SEED);
+        instances[0].points[3] = G1Affine::identity();
+        let expected = instances[0].compute_msm_baseline().into_affine();
+        // Invalid points must survive the round trip so that they can be reported.
+        let x = instances[1].points[2].x;
+        instances[1].points[2] = G1Affine::new_unchecked(x, x);
+
+        let mut buffer = Vec::new();
+        write_json_line(&mut buffer, &instances[0], Some(&expected))?;
+        write_json_line(&mut buffer, &instances[1], None)?;
+
+        let read = read_json_lines(buffer.as_slice()).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(read.len(), 2);
+        for (json, instance) in read.iter().zip(instances.iter()) {
+            let converted = json.to_instance(Validation::Unchecked)?;
+            assert_eq!(converted.points, instance.points);
+            assert_eq!(converted.scalars, instance.scalars);
+        }
+        assert_eq!(read[0].expected()?, Some(expected));
+        assert_eq!(read[1].expected()?, None);
+
+        assert!(read[0].to_instance(Validation::Checked).is_ok());
+        assert!(matches!(
+            read[1].to_instance(Validation::Checked),
+            Err(Error::PointNotOnCurve { index: 2 })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        let json = r#"{"points":[{"x":"0xzz","y":"0x1"}],"scalars":["0x1"]}"#;
+        let instance: JsonInstance = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            instance.to_instance(Validation::Unchecked),
+            Err(Error::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn scalars_are_only_range_checked_when_validating() -> Result<(), Error> {
+        let json = format!(
+            r#"{{"points":[{{"infinity":true}},{{"infinity":true}}],"scalars":["0x2a","0x{}"]}}"#,
+            "f".repeat(64)
+        );
+        let instance: JsonInstance = serde_json::from_str(&json)?;
+        let unchecked = instance.to_instance(Validation::Unchecked)?;
+        assert_eq!(unchecked.scalars[0], BigInt::from(42u64));
+        assert_eq!(unchecked.scalars[1], BigInt::new([u64::MAX; 4]));
+        // The scalar survives the round trip unchanged.
+        assert_eq!(
+            JsonInstance::new(&unchecked, None).scalars[1],
+            instance.scalars[1]
+        );
+        assert!(matches!(
+            instance.to_instance(Validation::Checked),
+            Err(Error::ScalarOutOfRange { index: 1 })
+        ));
+        Ok(())
+    }
+}
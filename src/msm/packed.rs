@@ -0,0 +1,145 @@
+//! Flat byte encodings of point and scalar vectors, for bulk transfer between JS and wasm.
+//!
+//! Points take 96 bytes each, in the uncompressed arkworks encoding: the x and y coordinates as
+//! little-endian integers, with the infinity flag in the top bits of the last byte. Scalars take 32
+//! bytes each, as little-endian integers. Both are the same encodings used in instance files.
+
+use super::{validate_points, validate_scalars, BigInt, Error, G1Affine, Validation};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+
+/// Length in bytes of a packed point.
+pub const POINT_LEN: usize = 96;
+
+/// Length in bytes of a packed scalar.
+pub const SCALAR_LEN: usize = 32;
+
+// The scalar view below relies on a scalar being exactly its limbs, with nothing in between.
+const _: () = assert!(std::mem::size_of::<BigInt>() == SCALAR_LEN);
+
+/// Pack points into a single buffer.
+pub fn pack_points(points: &[G1Affine]) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(points.len() * POINT_LEN);
+    for point in points {
+        point.serialize_unchecked(&mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+/// Unpack points from a single buffer, validating them if requested.
+pub fn unpack_points(bytes: &[u8], validation: Validation) -> Result<Vec<G1Affine>, Error> {
+    let points = chunks(bytes, POINT_LEN)?
+        .map(G1Affine::deserialize_unchecked)
+        .collect::<Result<Vec<_>, _>>()?;
+    if validation == Validation::Checked {
+        validate_points(&points)?;
+    }
+    Ok(points)
+}
+
+/// Pack scalars into a single buffer.
+pub fn pack_scalars(scalars: &[BigInt]) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(scalars.len() * SCALAR_LEN);
+    for scalar in scalars {
+        scalar.serialize(&mut bytes)?;
+    }
+    Ok(bytes)
+}
+
+/// Unpack scalars from a single buffer, validating them if requested.
+pub fn unpack_scalars(bytes: &[u8], validation: Validation) -> Result<Vec<BigInt>, Error> {
+    let scalars = chunks(bytes, SCALAR_LEN)?
+        .map(BigInt::deserialize)
+        .collect::<Result<Vec<_>, _>>()?;
+    if validation == Validation::Checked {
+        validate_scalars(&scalars)?;
+    }
+    Ok(scalars)
+}
+
+/// View scalars in place as their packed encoding, without copying.
+///
+/// A scalar is stored as little-endian 64-bit limbs, so on little-endian targets, including wasm,
+/// its memory is exactly its packed encoding.
+#[cfg(target_endian = "little")]
+pub fn scalars_as_bytes(scalars: &[BigInt]) -> &[u8] {
+    // Safety: BigInt is plain integer data of size SCALAR_LEN, as asserted above, so every byte of
+    // the slice is initialized and the length covers exactly the given scalars.
+    unsafe { std::slice::from_raw_parts(scalars.as_ptr() as *const u8, scalars.len() * SCALAR_LEN) }
+}
+
+/// View scalars in place as their packed encoding, allowing them to be written without copying.
+///
+/// Any bytes may be written, so the scalars must be validated afterwards if they are untrusted.
+#[cfg(target_endian = "little")]
+pub fn scalars_as_bytes_mut(scalars: &mut [BigInt]) -> &mut [u8] {
+    // Safety: as for scalars_as_bytes. Every bit pattern is a valid BigInt.
+    unsafe {
+        std::slice::from_raw_parts_mut(scalars.as_mut_ptr() as *mut u8, scalars.len() * SCALAR_LEN)
+    }
+}
+
+fn chunks(bytes: &[u8], len: usize) -> Result<std::slice::ChunksExact<'_, u8>, Error> {
+    if bytes.len() % len != 0 {
+        return Err(Error::InvalidByteLength {
+            expected: len,
+            found: bytes.len(),
+        });
+    }
+    Ok(bytes.chunks_exact(len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{generate_instances, ScalarField, DEFAULT_SEED};
+    use ark_ff::PrimeField;
+
+    #[test]
+    fn packed_round_trip() -> Result<(), Error> {
+        let mut instance = generate_instances(1, 1 << 6, DEFAULT_SEED).remove(0);
+        instance.points[5] = G1Affine::identity();
+
+        let bytes = pack_points(&instance.points)?;
+        assert_eq!(bytes.len(), instance.points.len() * POINT_LEN);
+        assert_eq!(unpack_points(&bytes, Validation::Checked)?, instance.points);
+        assert!(matches!(
+            unpack_points(&bytes[1..], Validation::Checked),
+            Err(Error::InvalidByteLength { .. })
+        ));
+
+        let bytes = pack_scalars(&instance.scalars)?;
+        assert_eq!(bytes, scalars_as_bytes(&instance.scalars));
+        assert_eq!(
+            unpack_scalars(&bytes, Validation::Checked)?,
+            instance.scalars
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_values_are_only_accepted_unchecked() -> Result<(), Error> {
+        let mut instance = generate_instances(1, 1 << 4, DEFAULT_SEED).remove(0);
+        let x = instance.points[2].x;
+        instance.points[2] = G1Affine::new_unchecked(x, x);
+        let bytes = pack_points(&instance.points)?;
+        assert!(matches!(
+            unpack_points(&bytes, Validation::Checked),
+            Err(Error::PointNotOnCurve { index: 2 })
+        ));
+        assert_eq!(
+            unpack_points(&bytes, Validation::Unchecked)?,
+            instance.points
+        );
+
+        // Writing through the mutable view changes the scalars in place.
+        let mut scalars = vec![BigInt::default(); 4];
+        scalars_as_bytes_mut(&mut scalars)[SCALAR_LEN..2 * SCALAR_LEN].fill(0xff);
+        assert!(matches!(
+            unpack_scalars(scalars_as_bytes(&scalars), Validation::Checked),
+            Err(Error::ScalarOutOfRange { index: 1 })
+        ));
+        assert!(validate_scalars(&scalars).is_err());
+        assert!(ScalarField::from_bigint(scalars[0]).is_some());
+        Ok(())
+    }
+}
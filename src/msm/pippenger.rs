@@ -0,0 +1,526 @@
+//! The bucket-method kernel with batch-affine accumulation, split into resumable steps.
+//!
+//! Scalars are split into windows of `c` bits, with unsigned digits or signed digits in
+//! `[-2^(c-1), 2^(c-1)]`. Within a window, points are sorted into buckets by digit, and each bucket
+//! is summed by adding its points in pairs, round by round, in affine coordinates. All of the
+//! additions of a round share a single batch inversion, which makes each of them much cheaper than
+//! a projective addition. This is the accumulation `compute_msm::<_, true, _>` uses, but exposed one
+//! window, or one slice of points within a window, at a time, so that the work can be paused, split
+//! between threads or workers, and run on signed digits.
+//!
+//! The buckets of a window are then summed with a running sum, and the window sums combined by
+//! doubling. [MsmJob] drives these steps as a resumable computation, so that a caller with a time
+//! budget, such as the browser main thread, can interleave it with other work.
+
+use super::{scalar_window, BigInt, Error, G1Affine, G1Projective, ScalarField};
+use ark_bls12_381::Fq;
+use ark_ec::ProjectiveCurve;
+use ark_ff::{batch_inversion, Field, PrimeField, Zero};
+use std::ops::Range;
+
+/// Largest window size supported. Every window keeps 2^c buckets, so larger windows would take more
+/// memory than they could ever save in time.
+pub const MAX_WINDOW: u32 = 20;
+
+/// Check that a window size is between 1 and [MAX_WINDOW].
+pub fn check_window(c: u32) -> Result<(), Error> {
+    if (1..=MAX_WINDOW).contains(&c) {
+        Ok(())
+    } else {
+        Err(Error::InvalidWindow { c })
+    }
+}
+
+/// Default window size for an MSM of the given size, following the heuristic used by arkworks, up
+/// to [MAX_WINDOW].
+pub fn default_window(size: usize) -> u32 {
+    if size < 32 {
+        3
+    } else {
+        // Approximately ln(size), computed without floating point.
+        ((usize::BITS - size.leading_zeros()) * 69 / 100 + 2).min(MAX_WINDOW)
+    }
+}
+
+/// Number of windows of `c` bits needed to cover a scalar.
+pub fn window_count(c: u32) -> u32 {
+    (ScalarField::MODULUS_BIT_SIZE + c - 1) / c
+}
+
+/// Number of windows of `c` bits needed to cover a scalar recoded into signed digits, which may be
+/// one more than for unsigned digits to hold the final carry.
+pub fn signed_window_count(c: u32) -> u32 {
+    ScalarField::MODULUS_BIT_SIZE / c + 1
+}
+
+/// Number of buckets per window: one per nonzero digit, or per nonzero absolute value of a signed
+/// digit.
+pub fn bucket_count(c: u32, signed: bool) -> usize {
+    if signed {
+        1 << (c - 1)
+    } else {
+        (1 << c) - 1
+    }
+}
+
+/// The signed digit of a scalar in the given window of `c` bits, in `[-2^(c-1), 2^(c-1)]`.
+///
+/// A window whose top bit is set borrows `2^c` from the next window, which in turn adds the
+/// borrowed one to its own digit. As that only depends on the top bit of the previous window, each
+/// digit can be computed on its own. Scalars must be below 2^(`MODULUS_BIT_SIZE`), as for any
+/// valid scalar, so that the top window never borrows.
+pub fn signed_digit(scalar: &BigInt, window: u32, c: u32) -> i64 {
+    let start = window * c;
+    let digit = scalar_window(scalar, start, c) as i64;
+    let carry = match start {
+        0 => 0,
+        _ => scalar_window(scalar, start - 1, 1) as i64,
+    };
+    let borrow = (digit >> (c - 1)) & 1;
+    digit + carry - (borrow << c)
+}
+
+/// The bucket a scalar puts its point in for the given window, and whether the point is negated,
+/// or `None` for a zero digit. Bucket `i` collects the points whose digit is `i + 1`, or `±(i + 1)`
+/// for signed digits.
+fn bucket_of(scalar: &BigInt, window: u32, c: u32, signed: bool) -> Option<(usize, bool)> {
+    if signed {
+        let digit = signed_digit(scalar, window, c);
+        (digit != 0).then(|| (digit.unsigned_abs() as usize - 1, digit < 0))
+    } else {
+        let digit = scalar_window(scalar, window * c, c);
+        (digit != 0).then(|| (digit - 1, false))
+    }
+}
+
+/// Add the points into the buckets of the given window, with batch-affine accumulation. The buckets
+/// may already hold sums from earlier slices of the points.
+pub fn accumulate_window(
+    buckets: &mut [G1Affine],
+    points: &[G1Affine],
+    scalars: &[BigInt],
+    window: u32,
+    c: u32,
+    signed: bool,
+) {
+    // Each bucket's current sum goes in first, so that it is added to like any other point.
+    let current = buckets
+        .iter()
+        .enumerate()
+        .filter(|(_, bucket)| !bucket.infinity)
+        .map(|(i, bucket)| (i, *bucket));
+    let placed = points
+        .iter()
+        .zip(scalars)
+        .filter(|(point, _)| !point.infinity)
+        .filter_map(|(point, scalar)| {
+            let (bucket, negate) = bucket_of(scalar, window, c, signed)?;
+            Some((bucket, if negate { -*point } else { *point }))
+        });
+    let sums = sum_into_buckets(buckets.len(), current.chain(placed).collect());
+    buckets.copy_from_slice(&sums);
+}
+
+/// Sum the points placed in each of `bucket_count` buckets, given as `(bucket, point)` pairs, with
+/// batch-affine accumulation. Empty buckets are left at the identity.
+pub(crate) fn sum_into_buckets(
+    bucket_count: usize,
+    placed: Vec<(usize, G1Affine)>,
+) -> Vec<G1Affine> {
+    // Counting sort by bucket.
+    let mut offsets = vec![0; bucket_count + 1];
+    for (bucket, _) in &placed {
+        offsets[bucket + 1] += 1;
+    }
+    for i in 1..offsets.len() {
+        offsets[i] += offsets[i - 1];
+    }
+    let mut sorted = vec![G1Affine::identity(); offsets[bucket_count]];
+    let mut next = offsets.clone();
+    for (bucket, point) in placed {
+        sorted[next[bucket]] = point;
+        next[bucket] += 1;
+    }
+    sum_buckets(sorted, offsets)
+}
+
+/// Sum each bucket, where bucket `i` holds `points[offsets[i]..offsets[i + 1]]`, by adding
+/// neighbouring points in pairs until every bucket has at most one point.
+pub(crate) fn sum_buckets(mut points: Vec<G1Affine>, mut offsets: Vec<usize>) -> Vec<G1Affine> {
+    while offsets.windows(2).any(|bucket| bucket[1] - bucket[0] > 1) {
+        // Each pair is given by the index of its first point.
+        let pairs = offsets
+            .windows(2)
+            .flat_map(|bucket| {
+                let end = bucket[1];
+                (bucket[0]..end).step_by(2).filter(move |&i| i + 1 < end)
+            })
+            .collect::<Vec<_>>();
+        let mut sums = add_pairs(&points, &pairs).into_iter();
+
+        let mut next_points = Vec::with_capacity(points.len() - pairs.len());
+        let mut next_offsets = Vec::with_capacity(offsets.len());
+        next_offsets.push(0);
+        for bucket in offsets.windows(2) {
+            for i in (bucket[0]..bucket[1]).step_by(2) {
+                if i + 1 < bucket[1] {
+                    next_points.push(sums.next().unwrap());
+                } else {
+                    next_points.push(points[i]);
+                }
+            }
+            next_offsets.push(next_points.len());
+        }
+        points = next_points;
+        offsets = next_offsets;
+    }
+
+    offsets
+        .windows(2)
+        .map(|bucket| {
+            if bucket[1] > bucket[0] {
+                points[bucket[0]]
+            } else {
+                G1Affine::identity()
+            }
+        })
+        .collect()
+}
+
+/// Add the points at `i` and `i + 1` for each `i` in `pairs`, in affine coordinates, sharing one
+/// inversion between all of the additions.
+fn add_pairs(points: &[G1Affine], pairs: &[usize]) -> Vec<G1Affine> {
+    let mut inverses = pairs
+        .iter()
+        .map(|&i| {
+            let (a, b) = (&points[i], &points[i + 1]);
+            if a.infinity || b.infinity || (a.x == b.x && a.y != b.y) {
+                Fq::zero()
+            } else if a.x == b.x {
+                a.y.double()
+            } else {
+                b.x - a.x
+            }
+        })
+        .collect::<Vec<_>>();
+    batch_inversion(&mut inverses);
+
+    pairs
+        .iter()
+        .zip(inverses)
+        .map(|(&i, inverse)| {
+            let (a, b) = (&points[i], &points[i + 1]);
+            if a.infinity {
+                return *b;
+            }
+            if b.infinity {
+                return *a;
+            }
+            let lambda = if a.x != b.x {
+                (b.y - a.y) * inverse
+            } else if a.y == b.y {
+                let x_squared = a.x.square();
+                (x_squared.double() + x_squared) * inverse
+            } else {
+                return G1Affine::identity();
+            };
+            let x = lambda.square() - a.x - b.x;
+            let y = lambda * (a.x - x) - a.y;
+            G1Affine::new_unchecked(x, y)
+        })
+        .collect()
+}
+
+/// Sum the buckets of a window, weighting bucket `i` by `i + 1`, with a running sum.
+pub fn reduce_buckets(buckets: &[G1Affine]) -> G1Projective {
+    let mut running_sum = G1Projective::zero();
+    let mut sum = G1Projective::zero();
+    for bucket in buckets.iter().rev() {
+        if !bucket.infinity {
+            running_sum.add_assign_mixed(bucket);
+        }
+        sum += &running_sum;
+    }
+    sum
+}
+
+/// Combine window sums, given lowest window first, into the final result.
+pub fn combine_windows(window_sums: &[G1Projective], c: u32) -> G1Projective {
+    let mut result = G1Projective::zero();
+    for sum in window_sums.iter().rev() {
+        for _ in 0..c {
+            result.double_in_place();
+        }
+        result += sum;
+    }
+    result
+}
+
+/// Sums of the given windows, each computed independently of the others, so that the windows of
+/// an MSM can be split between threads.
+pub fn window_sums(
+    points: &[G1Affine],
+    scalars: &[BigInt],
+    c: u32,
+    windows: Range<u32>,
+    signed: bool,
+) -> Vec<G1Projective> {
+    let mut buckets = vec![G1Affine::identity(); bucket_count(c, signed)];
+    windows
+        .map(|window| {
+            buckets.fill(G1Affine::identity());
+            accumulate_window(&mut buckets, points, scalars, window, c, signed);
+            reduce_buckets(&buckets)
+        })
+        .collect()
+}
+
+/// Split the windows of `c` bits into at most `parts` contiguous ranges of nearly equal length.
+pub fn split_windows(c: u32, parts: usize, signed: bool) -> Vec<Range<u32>> {
+    let windows = if signed {
+        signed_window_count(c)
+    } else {
+        window_count(c)
+    };
+    let parts = (parts.max(1) as u32).min(windows);
+    (0..parts)
+        .map(|i| (windows * i / parts)..(windows * (i + 1) / parts))
+        .collect()
+}
+
+/// Compute an MSM with the bucket method, with windows of `c` bits or the default for the size.
+pub fn compute_msm_buckets(
+    points: &[G1Affine],
+    scalars: &[BigInt],
+    c: Option<u32>,
+) -> Result<G1Projective, Error> {
+    let mut job = MsmJob::new(points.len(), c, false)?;
+    job.step(points, scalars, usize::MAX);
+    Ok(job.finish(points, scalars))
+}
+
+/// Compute an MSM with the bucket method on signed digits, with windows of `c` bits or the default
+/// for the size. This halves the number of buckets, and so the memory and work of reducing them,
+/// as negating a point is free.
+pub fn compute_msm_buckets_signed(
+    points: &[G1Affine],
+    scalars: &[BigInt],
+    c: Option<u32>,
+) -> Result<G1Projective, Error> {
+    let mut job = MsmJob::new(points.len(), c, true)?;
+    job.step(points, scalars, usize::MAX);
+    Ok(job.finish(points, scalars))
+}
+
+/// An MSM computed with the bucket method, a slice of points at a time.
+///
+/// The job does not hold the inputs. The same points and scalars must be passed to every call.
+#[derive(Clone, Debug)]
+pub struct MsmJob {
+    len: usize,
+    c: u32,
+    signed: bool,
+    /// Buckets of the window being processed.
+    buckets: Vec<G1Affine>,
+    /// Sums of the windows processed so far, lowest window first.
+    window_sums: Vec<G1Projective>,
+    /// Index of the next point to add in the current window.
+    next: usize,
+}
+
+impl MsmJob {
+    /// Start an MSM over `len` points, with windows of `c` bits or the default for the size, on
+    /// signed digits if `signed` is set.
+    pub fn new(len: usize, c: Option<u32>, signed: bool) -> Result<Self, Error> {
+        let c = c.unwrap_or_else(|| default_window(len));
+        check_window(c)?;
+        Ok(Self {
+            len,
+            c,
+            signed,
+            buckets: vec![G1Affine::identity(); bucket_count(c, signed)],
+            window_sums: Vec::with_capacity(signed_window_count(c) as usize),
+            next: 0,
+        })
+    }
+
+    pub fn window(&self) -> u32 {
+        self.c
+    }
+
+    fn windows(&self) -> u32 {
+        if self.signed {
+            signed_window_count(self.c)
+        } else {
+            window_count(self.c)
+        }
+    }
+
+    /// Process up to `budget` points, returning whether the job is complete.
+    pub fn step(&mut self, points: &[G1Affine], scalars: &[BigInt], budget: usize) -> bool {
+        assert_eq!(
+            points.len(),
+            self.len,
+            "points differ from those the job was created for"
+        );
+        assert_eq!(
+            scalars.len(),
+            self.len,
+            "scalars differ from those the job was created for"
+        );
+
+        let mut remaining = budget;
+        while !self.is_finished() && remaining > 0 {
+            let end = self.len.min(self.next.saturating_add(remaining));
+            accumulate_window(
+                &mut self.buckets,
+                &points[self.next..end],
+                &scalars[self.next..end],
+                self.window_sums.len() as u32,
+                self.c,
+                self.signed,
+            );
+            remaining -= end - self.next;
+            self.next = end;
+
+            if self.next == self.len {
+                self.window_sums.push(reduce_buckets(&self.buckets));
+                self.buckets.fill(G1Affine::identity());
+                self.next = 0;
+                // Count the reduction against the budget too, so that small instances with large
+                // windows still yield regularly.
+                remaining = remaining.saturating_sub(self.buckets.len());
+            }
+        }
+        self.is_finished()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.window_sums.len() as u32 == self.windows()
+    }
+
+    /// Fraction of the work done so far, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        if self.is_finished() {
+            return 1.0;
+        }
+        let done = self.window_sums.len() as f64 + self.next as f64 / self.len.max(1) as f64;
+        done / self.windows() as f64
+    }
+
+    /// Complete any remaining work and return the result.
+    pub fn finish(mut self, points: &[G1Affine], scalars: &[BigInt]) -> G1Projective {
+        self.step(points, scalars, usize::MAX);
+        combine_windows(&self.window_sums, self.c)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{compute_msm_baseline, mixed_inputs, test_inputs};
+
+    #[test]
+    fn buckets_match_baseline() -> Result<(), Error> {
+        for (size, scalars) in [(1, "uniform"), (100, "uniform"), (1 << 8, "mixed")] {
+            let (points, scalars) = test_inputs(size, scalars, "mixed");
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for c in [None, Some(1), Some(5), Some(12)] {
+                assert_eq!(compute_msm_buckets(&points, &scalars, c)?, baseline);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unrealistic_windows_are_rejected() {
+        for c in [0, MAX_WINDOW + 1, 31, 64] {
+            assert!(matches!(
+                MsmJob::new(10, Some(c), false),
+                Err(Error::InvalidWindow { .. })
+            ));
+            assert!(compute_msm_buckets_signed(&[], &[], Some(c)).is_err());
+        }
+    }
+
+    #[test]
+    fn signed_digits_recombine() {
+        let (_, mut scalars) = test_inputs(20, "uniform", "random");
+        scalars.push((-ScalarField::from(1u64)).into_bigint());
+        for c in [1, 2, 3, 5, 13, 16, MAX_WINDOW] {
+            for scalar in &scalars {
+                // Recombine the digits modulo the scalar field, which holds every valid scalar.
+                let mut recombined = ScalarField::zero();
+                for window in (0..signed_window_count(c)).rev() {
+                    let digit = signed_digit(scalar, window, c);
+                    assert!(digit.unsigned_abs() <= 1 << (c - 1), "c {}", c);
+                    for _ in 0..c {
+                        recombined.double_in_place();
+                    }
+                    let magnitude = ScalarField::from(digit.unsigned_abs());
+                    if digit < 0 {
+                        recombined -= magnitude;
+                    } else {
+                        recombined += magnitude;
+                    }
+                }
+                assert_eq!(recombined.into_bigint(), *scalar, "c {}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn signed_buckets_match_baseline() -> Result<(), Error> {
+        for scalars in ["uniform", "mixed", "minus-one", "window:5"] {
+            let (points, scalars) = test_inputs(100, scalars, "mixed");
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for c in [None, Some(1), Some(5), Some(12)] {
+                assert_eq!(compute_msm_buckets_signed(&points, &scalars, c)?, baseline);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn split_windows_combine_to_the_same_result() {
+        let (points, scalars) = test_inputs(100, "uniform", "random");
+        let baseline = compute_msm_baseline(&points, &scalars);
+        for (c, parts) in [(8, 1), (8, 3), (5, 7), (13, 100)] {
+            for signed in [false, true] {
+                let ranges = split_windows(c, parts, signed);
+                let sums = ranges
+                    .into_iter()
+                    .flat_map(|windows| window_sums(&points, &scalars, c, windows, signed))
+                    .collect::<Vec<_>>();
+                assert_eq!(combine_windows(&sums, c), baseline);
+            }
+            assert_eq!(
+                split_windows(c, parts, false).len(),
+                parts.min(window_count(c) as usize)
+            );
+        }
+    }
+
+    #[test]
+    fn stepping_gives_the_same_result() -> Result<(), Error> {
+        let (points, scalars) = mixed_inputs(100);
+
+        for signed in [false, true] {
+            let mut job = MsmJob::new(points.len(), Some(6), signed)?;
+            let mut steps = 0;
+            let mut progress = 0.0;
+            while !job.step(&points, &scalars, 37) {
+                assert!(job.progress() > progress);
+                progress = job.progress();
+                steps += 1;
+            }
+            assert!(steps > 1);
+            assert_eq!(job.progress(), 1.0);
+            assert_eq!(
+                job.finish(&points, &scalars),
+                compute_msm_baseline(&points, &scalars)
+            );
+        }
+        Ok(())
+    }
+}
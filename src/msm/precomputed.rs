@@ -0,0 +1,183 @@
+//! MSM against a fixed vector of bases, with their shifted multiples precomputed.
+//!
+//! For windows of `c` bits, storing `2^(c·j) · P` for every base `P` and window `j` turns an MSM
+//! into a single bucket pass over all windows at once: each digit adds the matching shifted base
+//! into its bucket, and one bucket reduction gives the result, with no doublings at all. This costs
+//! one point of memory per base per window, so it pays off when the same bases, such as the points
+//! of an SRS, are used for many MSMs.
+//!
+//! Tables are serialized as the window size as a little-endian `u32`, the number of bases as a
+//! little-endian `u64`, and then the shifted bases in the packed encoding of [super::packed].
+
+use super::packed::{pack_points, unpack_points, POINT_LEN};
+use super::pippenger::{check_window, reduce_buckets, sum_into_buckets, window_count, MAX_WINDOW};
+use super::{batch_normalize, scalar_window, BigInt, Error, G1Affine, G1Projective, Validation};
+use ark_ec::ProjectiveCurve;
+use ark_std::cfg_iter;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+const HEADER_LEN: usize = 12;
+
+/// Shifted multiples of a vector of bases, for MSMs against those bases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrecomputedBases {
+    c: u32,
+    len: usize,
+    /// `shifts[i · windows + j]` holds `2^(c·j)` times base `i`.
+    shifts: Vec<G1Affine>,
+}
+
+impl PrecomputedBases {
+    /// Precompute the shifted multiples of `bases` for windows of `c` bits, or of the size that
+    /// minimizes additions for MSMs over all of the bases.
+    pub fn new(bases: &[G1Affine], c: Option<u32>) -> Result<Self, Error> {
+        let c = c.unwrap_or_else(|| Self::window_for(bases.len()));
+        check_window(c)?;
+        let windows = window_count(c);
+        let shifts = cfg_iter!(bases)
+            .flat_map(|base| {
+                let mut shift = G1Projective::from(*base);
+                (0..windows)
+                    .map(|_| {
+                        let current = shift;
+                        for _ in 0..c {
+                            shift.double_in_place();
+                        }
+                        current
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            c,
+            len: bases.len(),
+            shifts: batch_normalize(&shifts),
+        })
+    }
+
+    /// The window size minimizing the additions of an MSM over `len` bases: one per base per
+    /// window, and two per bucket to reduce them.
+    pub fn window_for(len: usize) -> u32 {
+        (1..=MAX_WINDOW)
+            .min_by_key(|&c| len as u64 * window_count(c) as u64 + (2u64 << c))
+            .unwrap()
+    }
+
+    /// Number of bases.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn window(&self) -> u32 {
+        self.c
+    }
+
+    /// Compute the MSM of the bases with the given scalars. There may be fewer scalars than bases,
+    /// in which case only the first bases are used.
+    pub fn compute_msm(&self, scalars: &[BigInt]) -> G1Projective {
+        assert!(
+            scalars.len() <= self.len,
+            "{} scalars given for {} bases",
+            scalars.len(),
+            self.len
+        );
+        let windows = window_count(self.c);
+        let placed = self
+            .shifts
+            .chunks(windows as usize)
+            .zip(scalars)
+            .flat_map(|(shifts, scalar)| {
+                (0..windows).zip(shifts).filter_map(move |(window, shift)| {
+                    let digit = scalar_window(scalar, window * self.c, self.c);
+                    (digit != 0 && !shift.infinity).then(|| (digit - 1, *shift))
+                })
+            })
+            .collect();
+        let buckets = sum_into_buckets((1 << self.c) - 1, placed);
+        reduce_buckets(&buckets)
+    }
+
+    /// Serialize the table, so that it can be stored and loaded again without recomputing it.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.shifts.len() * POINT_LEN);
+        bytes.extend_from_slice(&self.c.to_le_bytes());
+        bytes.extend_from_slice(&(self.len as u64).to_le_bytes());
+        bytes.extend_from_slice(&pack_points(&self.shifts)?);
+        Ok(bytes)
+    }
+
+    /// Deserialize a table produced by [PrecomputedBases::to_bytes]. Checked validation checks
+    /// that every point is valid, but not that they are the multiples of each other they should be.
+    pub fn from_bytes(bytes: &[u8], validation: Validation) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::InvalidPrecomputedBases);
+        }
+        let (header, points) = bytes.split_at(HEADER_LEN);
+        let c = u32::from_le_bytes(header[..4].try_into().unwrap());
+        let len = u64::from_le_bytes(header[4..].try_into().unwrap());
+        check_window(c).map_err(|_| Error::InvalidPrecomputedBases)?;
+        let expected = (len as u128) * (window_count(c) as u128) * (POINT_LEN as u128);
+        if expected != points.len() as u128 {
+            return Err(Error::InvalidPrecomputedBases);
+        }
+        Ok(Self {
+            c,
+            len: len as usize,
+            shifts: unpack_points(points, validation)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{compute_msm_baseline, mixed_inputs, test_inputs};
+
+    #[test]
+    fn precomputed_matches_baseline() -> Result<(), Error> {
+        let (points, scalars) = mixed_inputs(200);
+        for c in [None, Some(1), Some(7), Some(16)] {
+            let bases = PrecomputedBases::new(&points, c)?;
+            assert_eq!(bases.len(), points.len());
+            assert_eq!(
+                bases.compute_msm(&scalars),
+                compute_msm_baseline(&points, &scalars)
+            );
+            // The same table serves MSMs over a prefix of the bases.
+            assert_eq!(
+                bases.compute_msm(&scalars[..50]),
+                compute_msm_baseline(&points[..50], &scalars[..50])
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn precomputed_round_trip() -> Result<(), Error> {
+        let (points, _) = test_inputs(20, "uniform", "random");
+        let bases = PrecomputedBases::new(&points, Some(9))?;
+        let bytes = bases.to_bytes()?;
+        assert_eq!(
+            PrecomputedBases::from_bytes(&bytes, Validation::Checked)?,
+            bases
+        );
+        let mut bad_window = bytes.clone();
+        bad_window[..4].copy_from_slice(&(MAX_WINDOW + 1).to_le_bytes());
+        for invalid in [&bytes[..5], &bytes[..bytes.len() - POINT_LEN], &bad_window] {
+            assert!(matches!(
+                PrecomputedBases::from_bytes(invalid, Validation::Checked),
+                Err(Error::InvalidPrecomputedBases)
+            ));
+        }
+        assert!(matches!(
+            PrecomputedBases::new(&points, Some(0)),
+            Err(Error::InvalidWindow { c: 0 })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+//! Straus' interleaved window method, for small MSMs.
+//!
+//! Each point gets a table of its first `2^w - 1` multiples. The scalars are then processed
+//! together from the top window down: the accumulator is doubled `w` times, and the table entry for
+//! each scalar's digit is added. This needs only `255` doublings in total, and no buckets, which
+//! makes it faster than Pippenger's algorithm for small inputs: up to [THRESHOLD] points.
+
+use super::pippenger::window_count;
+use super::{batch_normalize, scalar_window, BigInt, G1Affine, G1Projective};
+use ark_ec::ProjectiveCurve;
+use ark_ff::Zero;
+
+/// Window size. The cost per point of `2^w - 2` additions for the table and one addition per
+/// window is the same at every input size, and is lowest for 4 bits.
+pub const WINDOW: u32 = 4;
+
+/// Largest input that [super::compute_msm_dispatch] computes with this method.
+///
+/// Straus' method costs about 80 additions per point at any size: 14 for the table, its
+/// normalization, and one per 4-bit window. Pippenger's algorithm costs one addition per point and
+/// two per bucket in each of its `255 / c` windows, which at the default window comes to about 110
+/// per point at 128 points and 95 at 256, but its batch-affine additions are cheaper. The
+/// `small_msm` bench places the crossover, and this should be kept at the largest size at which
+/// `straus` still beats `opt` there.
+pub const THRESHOLD: usize = 128;
+
+/// Compute an MSM with Straus' method.
+pub fn compute_msm_straus(points: &[G1Affine], scalars: &[BigInt]) -> G1Projective {
+    assert_eq!(
+        points.len(),
+        scalars.len(),
+        "points and scalars differ in length"
+    );
+    // tables[i · digits + d - 1] holds d times point i.
+    let digits = (1 << WINDOW) - 1;
+    let multiples = points
+        .iter()
+        .flat_map(|point| {
+            let mut multiple = G1Projective::zero();
+            (0..digits).map(move |_| {
+                multiple.add_assign_mixed(point);
+                multiple
+            })
+        })
+        .collect::<Vec<_>>();
+    let tables = batch_normalize(&multiples);
+
+    let mut result = G1Projective::zero();
+    for window in (0..window_count(WINDOW)).rev() {
+        for _ in 0..WINDOW {
+            result.double_in_place();
+        }
+        for (table, scalar) in tables.chunks(digits).zip(scalars) {
+            let digit = scalar_window(scalar, window * WINDOW, WINDOW);
+            if digit != 0 {
+                result.add_assign_mixed(&table[digit - 1]);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{compute_msm_baseline, mixed_inputs};
+
+    #[test]
+    fn straus_matches_baseline_for_small_sizes() {
+        let (points, scalars) = mixed_inputs(256);
+        for size in 1..=256 {
+            let (points, scalars) = (&points[..size], &scalars[..size]);
+            assert_eq!(
+                compute_msm_straus(points, scalars),
+                compute_msm_baseline(points, scalars),
+                "size {}",
+                size
+            );
+        }
+    }
+}
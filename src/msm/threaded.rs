@@ -0,0 +1,179 @@
+//! Multi-threaded bucket-method MSM for native builds, with control over the number of threads and
+//! how the work is split between them.
+//!
+//! The work of an MSM is a grid of point chunks by windows. Each task computes the window sums of
+//! one chunk over a range of windows, and the sums for each window are then added across chunks.
+//! Splitting by window needs no extra additions but is limited to one task per window, while
+//! splitting by points parallelizes to any number of threads at the cost of reducing the buckets of
+//! every window once per chunk.
+
+use super::pippenger::{
+    check_window, combine_windows, default_window, split_windows, window_count, window_sums,
+};
+use super::{BigInt, Error, G1Affine, G1Projective};
+use ark_ff::Zero;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// How the work of an MSM is split between threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Give each thread a range of windows over all of the points.
+    ByWindow,
+    /// Give each thread a chunk of the points over all of the windows.
+    ByPoints,
+    /// Split by window, and also by points when there are more threads than windows.
+    Hybrid,
+}
+
+impl Default for SplitStrategy {
+    fn default() -> Self {
+        Self::Hybrid
+    }
+}
+
+/// A thread pool for computing MSMs with a given split strategy.
+pub struct ThreadedMsm {
+    pool: ThreadPool,
+    strategy: SplitStrategy,
+}
+
+impl ThreadedMsm {
+    /// Start a pool of `threads` threads, or one per core if `threads` is zero.
+    pub fn new(threads: usize, strategy: SplitStrategy) -> Result<Self, Error> {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        Ok(Self { pool, strategy })
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    pub fn strategy(&self) -> SplitStrategy {
+        self.strategy
+    }
+
+    /// Compute an MSM with windows of `c` bits, or the default for the size of each chunk. Each
+    /// task runs the batch-affine bucket kernel of [super::pippenger].
+    pub fn compute_msm(
+        &self,
+        points: &[G1Affine],
+        scalars: &[BigInt],
+        c: Option<u32>,
+    ) -> Result<G1Projective, Error> {
+        assert_eq!(
+            points.len(),
+            scalars.len(),
+            "points and scalars differ in length"
+        );
+        if let Some(c) = c {
+            check_window(c)?;
+        }
+        let threads = self.threads();
+        let (point_parts, c) = match self.strategy {
+            SplitStrategy::ByWindow => (1, c.unwrap_or_else(|| default_window(points.len()))),
+            SplitStrategy::ByPoints => {
+                let c = c.unwrap_or_else(|| default_window(div_ceil(points.len(), threads)));
+                (threads, c)
+            }
+            SplitStrategy::Hybrid => {
+                let c = c.unwrap_or_else(|| default_window(points.len()));
+                (div_ceil(threads, window_count(c) as usize), c)
+            }
+        };
+        let window_parts = div_ceil(threads, point_parts);
+        Ok(self
+            .pool
+            .install(|| compute_grid(points, scalars, c, point_parts, window_parts)))
+    }
+}
+
+fn div_ceil(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
+
+/// Compute an MSM as a grid of `point_parts` chunks of the points by `window_parts` ranges of the
+/// windows, with one task per cell.
+fn compute_grid(
+    points: &[G1Affine],
+    scalars: &[BigInt],
+    c: u32,
+    point_parts: usize,
+    window_parts: usize,
+) -> G1Projective {
+    let chunk = div_ceil(points.len(), point_parts).max(1);
+    let ranges = split_windows(c, window_parts, false);
+    let tasks = (0..points.len())
+        .step_by(chunk)
+        .flat_map(|start| ranges.iter().map(move |windows| (start, windows.clone())))
+        .collect::<Vec<_>>();
+
+    let results = tasks
+        .into_par_iter()
+        .map(|(start, windows)| {
+            let end = points.len().min(start + chunk);
+            let first = windows.start as usize;
+            (
+                first,
+                window_sums(&points[start..end], &scalars[start..end], c, windows, false),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut totals = vec![G1Projective::zero(); window_count(c) as usize];
+    for (first, sums) in results {
+        for (total, sum) in totals[first..].iter_mut().zip(&sums) {
+            *total += sum;
+        }
+    }
+    combine_windows(&totals, c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{compute_msm_baseline, mixed_inputs};
+
+    #[test]
+    fn strategies_match_baseline() -> Result<(), Error> {
+        let strategies = [
+            SplitStrategy::ByWindow,
+            SplitStrategy::ByPoints,
+            SplitStrategy::Hybrid,
+        ];
+        for size in [0, 1, 100, 1 << 10] {
+            let (points, scalars) = mixed_inputs(size);
+            let baseline = compute_msm_baseline(&points, &scalars);
+            for threads in [1, 3, 8, 64] {
+                for strategy in strategies {
+                    let msm = ThreadedMsm::new(threads, strategy)?;
+                    assert_eq!(msm.threads(), threads);
+                    for c in [None, Some(4)] {
+                        assert_eq!(
+                            msm.compute_msm(&points, &scalars, c)?,
+                            baseline,
+                            "size {}, {} threads, {:?}, c {:?}",
+                            size,
+                            threads,
+                            strategy,
+                            c
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unrealistic_windows_are_rejected() -> Result<(), Error> {
+        let msm = ThreadedMsm::new(2, SplitStrategy::default())?;
+        for c in [0, 21, 32, 64] {
+            assert!(matches!(
+                msm.compute_msm(&[], &[], Some(c)),
+                Err(Error::InvalidWindow { .. })
+            ));
+        }
+        Ok(())
+    }
+}
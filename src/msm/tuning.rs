@@ -0,0 +1,249 @@
+//! Empirical tuning of the window size `c` used by [compute_msm](super::compute_msm).
+//!
+//! The best window depends on the input size and on the relative cost of additions and memory on
+//! the platform, which differs a lot between native and wasm. [tune_window_table] measures each
+//! candidate window on generated instances of each size, and records the fastest in a
+//! [WindowTable]. Callers can pass the window from [WindowTable::lookup] to `compute_msm`
+//! explicitly, or opt in to installing the table process-wide with [set_window_table], after which
+//! `compute_msm` uses it whenever no window is given. Nothing installs a table implicitly: native
+//! callers load the file written by `tune-window-size` themselves, as the benches do with
+//! [read_window_table_if_exists].
+//!
+//! Tables are stored as text, one `<log2 size> <c>` pair per line, with `#` starting a comment.
+
+use super::pippenger::MAX_WINDOW;
+use super::{Error, Instance, DEFAULT_SEED};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+/// Best window size by the log2 of the input size.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WindowTable {
+    windows: BTreeMap<u32, u32>,
+}
+
+impl WindowTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the window for inputs of size `2^log_size`.
+    pub fn insert(&mut self, log_size: u32, c: u32) {
+        self.windows.insert(log_size, c);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// The window recorded for the size closest to `size`, on a log scale, if any.
+    pub fn lookup(&self, size: usize) -> Option<u32> {
+        if size == 0 {
+            return None;
+        }
+        let log_size = usize::BITS - 1 - size.leading_zeros();
+        let below = self.windows.range(..=log_size).next_back();
+        let above = self.windows.range(log_size + 1..).next();
+        let nearest = match (below, above) {
+            (Some(below), Some(above)) if above.0 - log_size < log_size - below.0 => above,
+            (Some(below), _) => below,
+            (None, above) => above?,
+        };
+        Some(*nearest.1)
+    }
+}
+
+impl fmt::Display for WindowTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# log2(size) c")?;
+        for (log_size, c) in &self.windows {
+            writeln!(f, "{} {}", log_size, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for WindowTable {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let mut table = Self::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || Error::InvalidWindowTable { line: i + 1 };
+            let mut fields = line.split_whitespace().map(|field| field.parse::<u32>());
+            let (log_size, c) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(log_size)), Some(Ok(c)), None) => (log_size, c),
+                _ => return Err(invalid()),
+            };
+            if log_size >= usize::BITS || !(1..=MAX_WINDOW).contains(&c) {
+                return Err(invalid());
+            }
+            table.insert(log_size, c);
+        }
+        Ok(table)
+    }
+}
+
+/// Sizes, windows and effort for [tune_window_table].
+#[derive(Clone, Debug)]
+pub struct TuningConfig {
+    /// Input sizes to tune, as log2 of the number of points.
+    pub log_sizes: Vec<u32>,
+    /// Windows to try at each size, within 1 to [MAX_WINDOW].
+    pub candidates: RangeInclusive<u32>,
+    /// Number of timed runs of each window. The median is compared.
+    pub rounds: usize,
+    /// Seed for the generated instances.
+    pub seed: u64,
+}
+
+impl Default for TuningConfig {
+    fn default() -> Self {
+        Self {
+            log_sizes: (8..=16).step_by(2).collect(),
+            candidates: 4..=16,
+            rounds: 5,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+/// Find the fastest window for each size in the config.
+///
+/// `time_msm(instance, c)` must compute the MSM of the instance with window `c` and return how
+/// long it took, in any unit. Timing is left to the caller, as clocks differ between platforms.
+pub fn tune_window_table<F>(config: &TuningConfig, mut time_msm: F) -> WindowTable
+where
+    F: FnMut(&Instance, u32) -> f64,
+{
+    let mut table = WindowTable::new();
+    for &log_size in &config.log_sizes {
+        let instance = Instance::generate_seeded(1 << log_size, config.seed, log_size as u64);
+        let best = config
+            .candidates
+            .clone()
+            .map(|c| (c, median_time(&mut time_msm, &instance, c, config.rounds)))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((c, _)) = best {
+            table.insert(log_size, c);
+        }
+    }
+    table
+}
+
+fn median_time<F>(time_msm: &mut F, instance: &Instance, c: u32, rounds: usize) -> f64
+where
+    F: FnMut(&Instance, u32) -> f64,
+{
+    let mut times = (0..rounds.max(1))
+        .map(|_| time_msm(instance, c))
+        .collect::<Vec<_>>();
+    times.sort_by(f64::total_cmp);
+    times[times.len() / 2]
+}
+
+static WINDOW_TABLE: RwLock<Option<WindowTable>> = RwLock::new(None);
+
+/// Install the table consulted by `compute_msm`, or remove it with `None`.
+pub fn set_window_table(table: Option<WindowTable>) {
+    *WINDOW_TABLE.write().unwrap() = table;
+}
+
+/// The table currently consulted by `compute_msm`, if any.
+pub fn window_table() -> Option<WindowTable> {
+    WINDOW_TABLE.read().unwrap().clone()
+}
+
+/// The window for an input of the given size according to the installed table, if any.
+pub fn tuned_window(size: usize) -> Option<u32> {
+    WINDOW_TABLE.read().unwrap().as_ref()?.lookup(size)
+}
+
+/// Default path of the table written by `tune-window-size`.
+pub const WINDOW_TABLE_PATH: &str = "window-table.txt";
+
+/// Read a table from a file.
+#[cfg(feature = "std")]
+pub fn read_window_table<P: AsRef<std::path::Path>>(path: P) -> Result<WindowTable, Error> {
+    std::fs::read_to_string(path)?.parse()
+}
+
+/// Read a table from a file, or return `None` if there is no such file.
+#[cfg(feature = "std")]
+pub fn read_window_table_if_exists<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<Option<WindowTable>, Error> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text.parse()?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a table to a file.
+#[cfg(feature = "std")]
+pub fn write_window_table<P: AsRef<std::path::Path>>(
+    path: P,
+    table: &WindowTable,
+) -> Result<(), Error> {
+    Ok(std::fs::write(path, table.to_string())?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table_round_trips_and_looks_up_nearest_size() -> Result<(), Error> {
+        let table =
+            "# comment\n8 6\n\n12 10  # trailing comment\n16 13\n".parse::<WindowTable>()?;
+        assert_eq!(table.to_string().parse::<WindowTable>()?, table);
+
+        assert_eq!(table.lookup(0), None);
+        assert_eq!(table.lookup(1), Some(6));
+        assert_eq!(table.lookup(1 << 10), Some(6));
+        assert_eq!(table.lookup((1 << 11) - 1), Some(10));
+        assert_eq!(table.lookup(1 << 12), Some(10));
+        assert_eq!(table.lookup(1 << 20), Some(13));
+        assert_eq!(WindowTable::new().lookup(1 << 12), None);
+
+        for invalid in ["8", "8 6 1", "8 x", "8 0", "8 21", "8 32", "64 8"] {
+            assert!(matches!(
+                invalid.parse::<WindowTable>(),
+                Err(Error::InvalidWindowTable { line: 1 })
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn tuning_picks_the_fastest_window() {
+        let config = TuningConfig {
+            log_sizes: vec![2, 5],
+            candidates: 3..=9,
+            rounds: 3,
+            seed: DEFAULT_SEED,
+        };
+        // Pretend the best window is log_size + 2, with one slow outlier per window that the median
+        // should ignore.
+        let mut runs = 0;
+        let table = tune_window_table(&config, |instance, c| {
+            runs += 1;
+            let best = instance.size().trailing_zeros() + 2;
+            if runs % 3 == 0 {
+                1000.0
+            } else {
+                (c as f64 - best as f64).abs()
+            }
+        });
+        assert_eq!(table.lookup(1 << 2), Some(4));
+        assert_eq!(table.lookup(1 << 5), Some(7));
+    }
+}
@@ -0,0 +1,260 @@
+//! Conversion to and from the ZCash serialization of BLS12-381, as specified in the IETF
+//! pairing-friendly curves draft and used by other libraries such as blst, noble-curves and py_ecc.
+//!
+//! Field elements and scalars are big-endian. The three most significant bits of the first byte of
+//! a point hold flags: whether the point is compressed, whether it is the point at infinity, and,
+//! for compressed points, whether y is the lexicographically larger of its two possible values.
+//!
+//! Points are always checked to be on the curve and in the prime-order subgroup when decoded, as
+//! the other libraries do.
+
+use super::{BigInt, Encoding, Error, G1Affine, ScalarField};
+use ark_bls12_381::Fq;
+use ark_ec::AffineCurve;
+use ark_ff::{BigInteger, PrimeField};
+
+/// Length in bytes of a field element.
+pub(super) const FQ_LEN: usize = 48;
+
+/// Length in bytes of a scalar.
+pub const SCALAR_LEN: usize = 32;
+
+const COMPRESSION_FLAG: u8 = 1 << 7;
+const INFINITY_FLAG: u8 = 1 << 6;
+const SORT_FLAG: u8 = 1 << 5;
+const FLAGS_MASK: u8 = COMPRESSION_FLAG | INFINITY_FLAG | SORT_FLAG;
+
+/// Length in bytes of a point in the given encoding.
+pub fn point_len(encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::Compressed => FQ_LEN,
+        Encoding::Uncompressed => 2 * FQ_LEN,
+    }
+}
+
+/// Serialize a single point.
+pub fn serialize_point(point: &G1Affine, encoding: Encoding) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(point_len(encoding));
+    write_point(&mut bytes, point, encoding);
+    bytes
+}
+
+/// Serialize a list of points, concatenating their encodings.
+pub fn serialize_points(points: &[G1Affine], encoding: Encoding) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(points.len() * point_len(encoding));
+    for point in points {
+        write_point(&mut bytes, point, encoding);
+    }
+    bytes
+}
+
+/// Deserialize a single point, checking that it is valid.
+pub fn deserialize_point(bytes: &[u8], encoding: Encoding) -> Result<G1Affine, Error> {
+    if bytes.len() != point_len(encoding) {
+        return Err(Error::InvalidByteLength {
+            expected: point_len(encoding),
+            found: bytes.len(),
+        });
+    }
+    read_point(bytes, encoding, 0)
+}
+
+/// Deserialize a concatenated list of points, checking that each is valid.
+pub fn deserialize_points(bytes: &[u8], encoding: Encoding) -> Result<Vec<G1Affine>, Error> {
+    let len = point_len(encoding);
+    if bytes.len() % len != 0 {
+        return Err(Error::InvalidByteLength {
+            expected: len,
+            found: bytes.len(),
+        });
+    }
+    bytes
+        .chunks_exact(len)
+        .enumerate()
+        .map(|(index, chunk)| read_point(chunk, encoding, index))
+        .collect()
+}
+
+/// Serialize a list of scalars as concatenated 32-byte big-endian integers.
+pub fn serialize_scalars(scalars: &[BigInt]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(scalars.len() * SCALAR_LEN);
+    for scalar in scalars {
+        bytes.extend_from_slice(&scalar.to_bytes_be());
+    }
+    bytes
+}
+
+/// Deserialize a list of concatenated 32-byte big-endian scalars, checking that each is below the
+/// scalar field modulus.
+pub fn deserialize_scalars(bytes: &[u8]) -> Result<Vec<BigInt>, Error> {
+    if bytes.len() % SCALAR_LEN != 0 {
+        return Err(Error::InvalidByteLength {
+            expected: SCALAR_LEN,
+            found: bytes.len(),
+        });
+    }
+    bytes
+        .chunks_exact(SCALAR_LEN)
+        .enumerate()
+        .map(|(index, chunk)| read_scalar(chunk, index))
+        .collect()
+}
+
+fn write_point(bytes: &mut Vec<u8>, point: &G1Affine, encoding: Encoding) {
+    let start = bytes.len();
+    if point.infinity {
+        bytes.resize(start + point_len(encoding), 0);
+        bytes[start] = INFINITY_FLAG;
+    } else {
+        bytes.extend_from_slice(&point.x.into_bigint().to_bytes_be());
+        match encoding {
+            Encoding::Compressed => {
+                if point.y > -point.y {
+                    bytes[start] |= SORT_FLAG;
+                }
+            }
+            Encoding::Uncompressed => bytes.extend_from_slice(&point.y.into_bigint().to_bytes_be()),
+        }
+    }
+    if encoding == Encoding::Compressed {
+        bytes[start] |= COMPRESSION_FLAG;
+    }
+}
+
+fn read_point(bytes: &[u8], encoding: Encoding, index: usize) -> Result<G1Affine, Error> {
+    let flags = bytes[0] & FLAGS_MASK;
+    let compressed = flags & COMPRESSION_FLAG != 0;
+    let infinity = flags & INFINITY_FLAG != 0;
+    let sort = flags & SORT_FLAG != 0;
+    if compressed != (encoding == Encoding::Compressed)
+        || (sort && (infinity || encoding == Encoding::Uncompressed))
+    {
+        return Err(Error::InvalidPointFlags { index });
+    }
+
+    let mut data = bytes.to_vec();
+    data[0] &= !FLAGS_MASK;
+    if infinity {
+        // The point at infinity must be encoded with all other bits zero.
+        if data.iter().any(|b| *b != 0) {
+            return Err(Error::InvalidPointFlags { index });
+        }
+        return Ok(G1Affine::identity());
+    }
+
+    let x = read_fq(&data[..FQ_LEN], index)?;
+    let point = match encoding {
+        Encoding::Compressed => {
+            G1Affine::get_point_from_x(x, sort).ok_or(Error::PointNotOnCurve { index })?
+        }
+        Encoding::Uncompressed => {
+            let point = G1Affine::new_unchecked(x, read_fq(&data[FQ_LEN..], index)?);
+            if !point.is_on_curve() {
+                return Err(Error::PointNotOnCurve { index });
+            }
+            point
+        }
+    };
+    if !point.is_in_correct_subgroup_assuming_on_curve() {
+        return Err(Error::PointNotInSubgroup { index });
+    }
+    Ok(point)
+}
+
+/// Read a big-endian base field element, rejecting values that are not below the modulus.
+pub(super) fn read_fq(bytes: &[u8], index: usize) -> Result<Fq, Error> {
+    // Reduction only changes the value if it was out of range, which the round trip detects.
+    let element = Fq::from_be_bytes_mod_order(bytes);
+    if element.into_bigint().to_bytes_be() != bytes {
+        return Err(Error::InvalidFieldElement { index });
+    }
+    Ok(element)
+}
+
+/// Read a big-endian scalar, rejecting values that are not below the modulus.
+pub(super) fn read_scalar(bytes: &[u8], index: usize) -> Result<BigInt, Error> {
+    let scalar = ScalarField::from_be_bytes_mod_order(bytes).into_bigint();
+    if scalar.to_bytes_be() != bytes {
+        return Err(Error::ScalarOutOfRange { index });
+    }
+    Ok(scalar)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::msm::{generate_instances, DEFAULT_SEED};
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // Encodings of the generator of G1 given in the ZCash specification.
+    const GENERATOR_X: &str = "17f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb";
+    const GENERATOR_Y: &str = "08b3f481e3aaa0f1a09e30ed741d8ae4fcf5e095d5d00af600db18cb2c04b3edd03cc744a2888ae40caa232946c5e7e1";
+
+    #[test]
+    fn generator_matches_reference_encoding() -> Result<(), Error> {
+        let generator = G1Affine::prime_subgroup_generator();
+
+        let mut compressed = from_hex(GENERATOR_X);
+        compressed[0] |= COMPRESSION_FLAG;
+        assert_eq!(
+            serialize_point(&generator, Encoding::Compressed),
+            compressed
+        );
+        assert_eq!(
+            deserialize_point(&compressed, Encoding::Compressed)?,
+            generator
+        );
+
+        let uncompressed = [from_hex(GENERATOR_X), from_hex(GENERATOR_Y)].concat();
+        assert_eq!(
+            serialize_point(&generator, Encoding::Uncompressed),
+            uncompressed
+        );
+        assert_eq!(
+            deserialize_point(&uncompressed, Encoding::Uncompressed)?,
+            generator
+        );
+
+        // The negated generator has the larger y-coordinate.
+        compressed[0] |= SORT_FLAG;
+        assert_eq!(
+            serialize_point(&-generator, Encoding::Compressed),
+            compressed
+        );
+
+        let mut infinity = vec![0u8; 48];
+        infinity[0] = COMPRESSION_FLAG | INFINITY_FLAG;
+        assert_eq!(
+            serialize_point(&G1Affine::identity(), Encoding::Compressed),
+            infinity
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn points_and_scalars_round_trip() -> Result<(), Error> {
+        let instance = &generate_instances(1, 1 << 6, DEFAULT_SEED)[0];
+        for encoding in [Encoding::Compressed, Encoding::Uncompressed] {
+            let bytes = serialize_points(&instance.points, encoding);
+            assert_eq!(deserialize_points(&bytes, encoding)?, instance.points);
+            assert!(deserialize_points(&bytes[1..], encoding).is_err());
+        }
+
+        let bytes = serialize_scalars(&instance.scalars);
+        assert_eq!(deserialize_scalars(&bytes)?, instance.scalars);
+
+        // The modulus itself is out of range.
+        let modulus = serialize_scalars(&[ScalarField::MODULUS]);
+        assert!(matches!(
+            deserialize_scalars(&modulus),
+            Err(Error::ScalarOutOfRange { index: 0 })
+        ));
+        Ok(())
+    }
+}
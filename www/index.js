@@ -1,19 +1,26 @@
-import { compute_msm_baseline, compute_msm, compute_msm_with_c, generate_msm_inputs, deserialize_msm_inputs, ScalarVectorInput, PointVectorInput, minicov_capture_coverage } from "wasm-prover";
+import { compute_msm_baseline_instance, compute_msm_instance, generate_msm_inputs, InstanceStream, minicov_capture_coverage, set_window_table, tune_window_table } from "wasm-prover";
 
 const outputPre = document.getElementById("wasm-prover");
 const instanceInput = document.getElementById("instance-file");
 const runButtonOpt = document.getElementById("run-button-opt");
 const runButtonBase = document.getElementById("run-button-baseline");
 const profileButton = document.getElementById("profile-button");
+const tuneButton = document.getElementById("tune-button");
 
 // Parameters for generated MSM inputs.
 const MSM_GENERATE_NUM = 10;
 const MSM_GENERATE_SIZE = 8;
+const MSM_GENERATE_SEED = 0n;
 
-const ADJUST_C = false;
-const MSM_C_MIN = 12;
-const MSM_C_STEP = 1;
-const MSM_C_MAX = 14;
+// Input sizes, as powers of two, and window sizes tried when tuning c.
+const TUNE_SIZE_MIN = 8;
+const TUNE_SIZE_MAX = 16;
+const TUNE_C_MIN = 4;
+const TUNE_C_MAX = 16;
+const TUNE_ROUNDS = 3;
+
+// Local storage key for the window table found by tuning, which is installed again on load.
+const WINDOW_TABLE_KEY = "msm-window-table";
 
 const MARK_START_DESERIALIZE = () => `Start deserialize input`;
 const MARK_STOP_DESERIALIZE = () => `Stop deserialize input`;
@@ -50,74 +57,81 @@ function buffer2hex(buffer) {
   return hexarray.join('')
 }
 
-async function deserialize_file_input() {
-  // If there is no file input, return undefined.
-  if (instanceInput.files.length == 0) {
-    return undefined
-  }
-
-  const file = instanceInput.files.item(0)
-  const data = await file.arrayBuffer()
-  // const hash = await crypto.subtle.digest('SHA-256', data)
-  // console.log(`Instance input file ${file.name} is of length ${file.size} and hash: ${buffer2hex(hash)}`)
-  // Note that this returns an InstanceObjectVector.
-
-  performance.mark(MARK_START_DESERIALIZE())
-  const deserialized = deserialize_msm_inputs(new Uint8Array(data))
-  performance.mark(MARK_STOP_DESERIALIZE())
-  performance.measure(MEASURE_DESERIALIZE(), MARK_START_DESERIALIZE(), MARK_STOP_DESERIALIZE())
+// Yield the instances in the given file one at a time, reading it in chunks so that files too
+// large to fit into memory can be used.
+async function* stream_file_instances(file) {
+  const stream = new InstanceStream()
+  const reader = file.stream().getReader()
+  while (true) {
+    const { done, value } = await reader.read()
+    if (done) {
+      break
+    }
 
-  return deserialized
-}
+    performance.mark(MARK_START_DESERIALIZE())
+    stream.feed(value)
+    let instance = stream.next()
+    performance.mark(MARK_STOP_DESERIALIZE())
+    performance.measure(MEASURE_DESERIALIZE(), MARK_START_DESERIALIZE(), MARK_STOP_DESERIALIZE())
 
-async function load_or_generate_msm_inputs() {
-  // First check for a file input and deserialize it if one is provided.
-  const deserialized = await deserialize_file_input()
-  if (deserialized !== undefined) {
-    return deserialized
+    while (instance !== undefined) {
+      yield instance
+      instance = stream.next()
+    }
   }
+  stream.finish()
+  stream.free()
+}
 
-  // No file was provided, so we should generate new inputs.
-  const generated = Array.from({ length: MSM_GENERATE_NUM }, () => {
+// Yield generated instances one at a time.
+function* generate_instances() {
+  for (let i = 0; i < MSM_GENERATE_NUM; i++) {
     // Generating the input itself is actually a rather time consuming operation.
     performance.mark(MARK_START_GENERATE(MSM_GENERATE_SIZE))
-    const instance = generate_msm_inputs(Math.pow(2, MSM_GENERATE_SIZE))
+    const instance = generate_msm_inputs(Math.pow(2, MSM_GENERATE_SIZE), MSM_GENERATE_SEED, BigInt(i))
     performance.mark(MARK_STOP_GENERATE(MSM_GENERATE_SIZE))
     performance.measure(MEASURE_GENERATE(MSM_GENERATE_SIZE), MARK_START_GENERATE(MSM_GENERATE_SIZE), MARK_STOP_GENERATE(MSM_GENERATE_SIZE))
-    return instance
-  })
-  return generated
+    yield instance
+  }
 }
 
-async function wasm_bench_msm_with_c(instances, opt, c) {
-  const size = Math.floor(Math.log2(instances.at(0).length)) // Assume all instances as same size.
-
-  for (let j = 0; j < 1; j++) {
-    for (let i = 0; i < instances.length; i++) {
-      console.log(`Running benchmark with instance ${j}/${i} {c: ${c}}`)
-      const instance = instances.at(i)
-      const points = instance.points()
-      const scalars = instance.scalars()
-
-      // Measure the actual MSM computation.
-      performance.mark(MARK_START_MSM(size, c));
-      let result;
-      if (opt && c !== undefined) {
-        result = compute_msm_with_c(points, scalars, c)
-      } else if (opt) {
-        result = compute_msm(points, scalars)
-      } else {
-        result = compute_msm_baseline(points, scalars)
-      };
-      performance.mark(MARK_STOP_MSM(size, c));
-      performance.measure(MEASURE_MSM(size, c), MARK_START_MSM(size, c), MARK_STOP_MSM(size, c));
-    }
+// Yield the instances from the file input if one is provided, or generated instances otherwise.
+function msm_instances() {
+  if (instanceInput.files.length > 0) {
+    return stream_file_instances(instanceInput.files.item(0))
+  }
+  return generate_instances()
+}
+
+async function wasm_bench_msm_with_c(opt, c) {
+  let size = undefined
+  let i = 0
+
+  for await (const instance of msm_instances()) {
+    size = Math.floor(Math.log2(instance.length)) // Assume all instances as same size.
+    console.log(`Running benchmark with instance ${i} {c: ${c}}`)
+
+    // Measure the actual MSM computation. The instance is passed directly, so no data is copied.
+    performance.mark(MARK_START_MSM(size, c));
+    let result;
+    if (opt) {
+      result = compute_msm_instance(instance, c)
+    } else {
+      result = compute_msm_baseline_instance(instance)
+    };
+    performance.mark(MARK_STOP_MSM(size, c));
+    performance.measure(MEASURE_MSM(size, c), MARK_START_MSM(size, c), MARK_STOP_MSM(size, c));
+
+    // Release the wasm memory held by this instance before moving on to the next one.
+    result.free()
+    instance.free()
+    i++
   }
 
   // Extract the performance markers and format the aggregate result from all instances.
   const measures = performance.getEntriesByName(MEASURE_MSM(size, c), "measure");
   let durations = measures.map(({ duration }) => duration);
-  let cur_res = `\nbench_msm(). input vector length: 2^${size} {c: ${c}},\n  median performance: ${median(durations)} ms,\n  average performance: ${average(durations)} ms`;
+  let cur_res = `\nbench_msm(). ${i} instances, input vector length: 2^${size} {c: ${c}},\n  median performance: ${median(durations)} ms,\n  average performance: ${average(durations)} ms`;
   outputPre.textContent += cur_res;
   return cur_res;
 }
@@ -129,19 +143,8 @@ async function wasm_bench_msm(opt) {
   performance.clearMarks();
   performance.clearMeasures();
 
-  const instances = await load_or_generate_msm_inputs()
-  const size = Math.floor(Math.log2(instances.at(0).length)) // Assume all instances as same size.
-  console.log(`Running benchmark with ${instances.length} instances of size 2^${size}`)
-
-  // Note: Using a classic for loop because the Rust object, InstanceObjectVector, does not support
-  // the iterator interface.
-  if (opt && ADJUST_C) {
-    for (let c = MSM_C_MIN; c <= MSM_C_MAX; c += MSM_C_STEP) {
-      out_text += await wasm_bench_msm_with_c(instances, opt, c);
-    }
-  } else {
-    out_text += await wasm_bench_msm_with_c(instances, opt, undefined);
-  }
+  // Without an explicit c, compute_msm uses the window table installed by tuning, if any.
+  out_text += await wasm_bench_msm_with_c(opt, undefined);
   console.log(`Finished running benchmark`)
 
   return out_text;
@@ -155,16 +158,47 @@ function saveByteArray(filename, bytes) {
     link.click();
 };
 
+// Run the benchmark, reporting errors from invalid inputs on the page.
+async function run_bench(opt) {
+  try {
+    outputPre.textContent = await wasm_bench_msm(opt)
+  } catch (e) {
+    outputPre.textContent = `error: ${e.message}`
+    throw e
+  }
+}
+
+// Use the window table from an earlier run of the tuner, if any.
+const storedWindowTable = localStorage.getItem(WINDOW_TABLE_KEY)
+if (storedWindowTable !== null) {
+  set_window_table(storedWindowTable)
+}
+
+// Find the best c for each input size on this browser, and keep the table for later visits.
+tuneButton.onclick = async () => {
+  outputPre.textContent = `tuning c...`
+  // Let the page update before tuning blocks the main thread.
+  await new Promise(resolve => setTimeout(resolve, 0))
+  try {
+    const table = tune_window_table(TUNE_SIZE_MIN, TUNE_SIZE_MAX, TUNE_C_MIN, TUNE_C_MAX, TUNE_ROUNDS)
+    localStorage.setItem(WINDOW_TABLE_KEY, table)
+    outputPre.textContent = `window table:\n${table}`
+  } catch (e) {
+    outputPre.textContent = `error: ${e.message}`
+    throw e
+  }
+}
+
 // benchmarking msm opt
 runButtonOpt.onclick = async () => {
   outputPre.textContent = `running (opt)...`
-  outputPre.textContent = await wasm_bench_msm(true)
+  await run_bench(true)
 }
 
 // benchmarking msm baseline
 runButtonBase.onclick = async () => {
   outputPre.textContent = `running (baseline)...`
-  outputPre.textContent = await wasm_bench_msm(false)
+  await run_bench(false)
 }
 
 // benchmarking msm baseline
@@ -0,0 +1,9 @@
+// Web Worker entry point for the pool in pool.js.
+import * as pkg from "../../pkg-parallel/wasm_zkp_challenge.js";
+import { serveMsmTasks } from "./serve.js";
+
+serveMsmTasks(
+  pkg,
+  (message) => self.postMessage(message),
+  (callback) => self.addEventListener("message", (event) => callback(event.data))
+);
@@ -0,0 +1,87 @@
+// A pool of workers that compute MSMs together, sharing the wasm module's memory.
+//
+// Requires the package built by build-parallel.sh. The pool does not depend on the environment:
+// `spawn` starts one worker and returns an object with `postMessage(message)`,
+// `onMessage(callback)` and `terminate()`. `spawnBrowserWorker` does this for Web Workers, and
+// test/node.test.mjs does it for Node worker_threads.
+
+export class MsmWorkerPool {
+  constructor(pkg, workers) {
+    this.pkg = pkg;
+    this.workers = workers;
+  }
+
+  // Start `size` workers, each instantiating `module` on the shared `memory`, which are the
+  // compiled module and the memory export of the already initialized package `pkg`.
+  static async create(pkg, module, memory, size, spawn) {
+    const workers = await Promise.all(
+      Array.from({ length: size }, async () => {
+        const worker = new PoolWorker(spawn());
+        await worker.request({ type: "init", module, memory });
+        return worker;
+      })
+    );
+    return new MsmWorkerPool(pkg, workers);
+  }
+
+  get size() {
+    return this.workers.length;
+  }
+
+  // Compute the MSM of an InstanceObject, with its windows split between the workers. Resolves to
+  // a PointOutput, the same as compute_msm_instance, which remains the single-threaded fallback.
+  async computeMsm(instance, c) {
+    const job = new this.pkg.ParallelMsmJob(instance, c, this.size);
+    try {
+      const tasks = Array.from({ length: job.taskCount }, (_, task) => task);
+      await Promise.all(
+        tasks.map((task) => this.workers[task].request({ type: "run", job: job.id, task }))
+      );
+      return job.finish();
+    } finally {
+      job.free();
+    }
+  }
+
+  terminate() {
+    for (const worker of this.workers) {
+      worker.terminate();
+    }
+  }
+}
+
+// A worker that answers requests in the order they were sent.
+class PoolWorker {
+  constructor(worker) {
+    this.worker = worker;
+    this.pending = [];
+    worker.onMessage((message) => {
+      const { resolve, reject } = this.pending.shift();
+      if (message.error === undefined) {
+        resolve(message);
+      } else {
+        reject(new Error(message.error));
+      }
+    });
+  }
+
+  request(message) {
+    return new Promise((resolve, reject) => {
+      this.pending.push({ resolve, reject });
+      this.worker.postMessage(message);
+    });
+  }
+
+  terminate() {
+    this.worker.terminate();
+  }
+}
+
+export function spawnBrowserWorker() {
+  const worker = new Worker(new URL("./msm-worker.js", import.meta.url), { type: "module" });
+  return {
+    postMessage: (message) => worker.postMessage(message),
+    onMessage: (callback) => worker.addEventListener("message", (event) => callback(event.data)),
+    terminate: () => worker.terminate(),
+  };
+}
@@ -0,0 +1,19 @@
+// The worker side of the pool: instantiate the package on the shared memory, then run tasks.
+// `post` sends a message to the pool and `listen` registers a callback for messages from it.
+
+export function serveMsmTasks(pkg, post, listen) {
+  listen(async (message) => {
+    try {
+      if (message.type === "init") {
+        await pkg.default(message.module, message.memory);
+      } else if (message.type === "run") {
+        pkg.run_msm_task(message.job, message.task);
+      } else {
+        throw new Error(`unknown message type ${message.type}`);
+      }
+      post({ type: message.type });
+    } catch (e) {
+      post({ type: message.type, error: String(e?.message ?? e) });
+    }
+  });
+}
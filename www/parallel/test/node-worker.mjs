@@ -0,0 +1,10 @@
+// worker_threads entry point for the pool, used by node.test.mjs.
+import { parentPort } from "node:worker_threads";
+import * as pkg from "../../../pkg-parallel/wasm_zkp_challenge.js";
+import { serveMsmTasks } from "../serve.js";
+
+serveMsmTasks(
+  pkg,
+  (message) => parentPort.postMessage(message),
+  (callback) => parentPort.on("message", callback)
+);
@@ -0,0 +1,64 @@
+// Check the worker pool against the single-threaded MSM, headlessly under Node worker_threads.
+//
+//   ./build-parallel.sh && node --test www/parallel/test/
+import assert from "node:assert/strict";
+import { readFile } from "node:fs/promises";
+import { test } from "node:test";
+import { Worker } from "node:worker_threads";
+import * as pkg from "../../../pkg-parallel/wasm_zkp_challenge.js";
+import { MsmWorkerPool } from "../pool.js";
+
+function spawnNodeWorker() {
+  const worker = new Worker(new URL("./node-worker.mjs", import.meta.url));
+  return {
+    postMessage: (message) => worker.postMessage(message),
+    onMessage: (callback) => worker.on("message", callback),
+    terminate: () => worker.terminate(),
+  };
+}
+
+const wasmUrl = new URL("../../../pkg-parallel/wasm_zkp_challenge_bg.wasm", import.meta.url);
+const module = await WebAssembly.compile(await readFile(wasmUrl));
+const wasm = await pkg.default(module);
+
+const bytes = (output) => Array.from(output.toZcashBytes(true));
+
+test("parallel MSM matches compute_msm", async () => {
+  const pool = await MsmWorkerPool.create(pkg, module, wasm.memory, 4, spawnNodeWorker);
+  try {
+    for (const [size, c] of [[1, undefined], [100, 5], [1 << 12, undefined], [1 << 12, 13]]) {
+      const instance = pkg.generate_msm_inputs(size, 0n, 0n);
+      const expected = pkg.compute_msm_instance(instance, c);
+      const actual = await pool.computeMsm(instance, c);
+      assert.deepEqual(bytes(actual), bytes(expected), `size ${size}, c ${c}`);
+    }
+  } finally {
+    pool.terminate();
+  }
+});
+
+test("invalid window sizes are rejected without breaking the pool", async () => {
+  const pool = await MsmWorkerPool.create(pkg, module, wasm.memory, 2, spawnNodeWorker);
+  try {
+    const instance = pkg.generate_msm_inputs(64, 1n, 0n);
+    await assert.rejects(pool.computeMsm(instance, 0), /window size/);
+    const expected = pkg.compute_msm_baseline_instance(instance);
+    assert.deepEqual(bytes(await pool.computeMsm(instance, 8)), bytes(expected));
+  } finally {
+    pool.terminate();
+  }
+});
+
+test("tasks can only be run once, and only for a live job", () => {
+  const instance = pkg.generate_msm_inputs(64, 2n, 0n);
+  const job = new pkg.ParallelMsmJob(instance, 8, 2);
+  const id = job.id;
+  assert.throws(() => pkg.run_msm_task(id, job.taskCount), /no such task/);
+  for (let task = 0; task < job.taskCount; task++) {
+    pkg.run_msm_task(id, task);
+  }
+  assert.throws(() => pkg.run_msm_task(id, 0), /already been run/);
+  assert.deepEqual(bytes(job.finish()), bytes(pkg.compute_msm_baseline_instance(instance)));
+  job.free();
+  assert.throws(() => pkg.run_msm_task(id, 0), /no such parallel MSM job/);
+});